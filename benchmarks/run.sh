@@ -2,6 +2,7 @@ readonly RATES=(1000 10000 100000 1000000 2000000 4000000 8000000 10000000)
 readonly WORKERS=(32)
 readonly SECONDS=(300)
 readonly BENCHMARKS="HiBench,Yahoo,NEXMark"
+readonly TESTS=""
 readonly DIR=$(dirname "$(readlink -f "$0")")
 
 function status() {
@@ -18,15 +19,23 @@ function run() {
     local workers="$2"
     local seconds="$3"
     local benchmarks="$4"
+    local tests="$5"
+    local source="$6"
+    local suffix="$7"
     local output="$PWD"
-    cd "$DIR"
+    local filter=()
+    if [ -n "$tests" ]; then
+	filter=(--tests "$tests")
+    fi
+    cd "$source"
     cargo run --release -- test \
 	  --report "latencies" \
 	  --threads "$workers" \
 	  --seconds "$seconds" \
 	  --events-per-second "$rate" \
           --benchmarks "$benchmarks" \
-	  > "$output/$rate@$workers.csv" \
+	  "${filter[@]}" \
+	  > "$output/$rate@$workers$suffix.csv" \
 	|| eexit "Benchmark crashed."
 }
 
@@ -35,25 +44,44 @@ function main() {
     local workers=("${WORKERS[@]}")
     local seconds=("${SECONDS[@]}")
     local benchmarks="$BENCHMARKS"
+    local tests="$TESTS"
+    local revision=""
+    local source="$DIR"
+    local suffix=""
     
-    while getopts "r:w:s:b:" opt; do
+    while getopts "r:w:s:b:t:g:" opt; do
 	case "$opt" in
 	    r) rate=($OPTARG) ;;
 	    w) workers=($OPTARG) ;;
 	    s) seconds=($OPTARG) ;;
 	    b) benchmarks=$OPTARG ;;
+	    t) tests=$OPTARG ;;
+	    g) revision=$OPTARG ;;
 	    \?) >&2 echo "Invalid option: -$OPTARG"; exit 1;;
 	esac
     done
 
+    # Run the benchmarks of another revision from a separate work tree.
+    if [ -n "$revision" ]; then
+	local tree=$(mktemp -d)
+	git -C "$DIR" worktree add --detach "$tree" "$revision" \
+	    || eexit "Failed to check out $revision."
+	source="$tree/benchmarks"
+	suffix="-$revision"
+    fi
+
     for r in "${rate[@]}"; do
 	for w in "${workers[@]}"; do
 	    for s in "${seconds[@]}"; do
 		status "Running $w workers @ $r events/s for $s seconds."
-		run "$r" "$w" "$s" "$benchmarks"
+		run "$r" "$w" "$s" "$benchmarks" "$tests" "$source" "$suffix"
 	    done
 	done
     done
+
+    if [ -n "$revision" ]; then
+	git -C "$DIR" worktree remove --force "$tree"
+    fi
 }
 
 main "$@"
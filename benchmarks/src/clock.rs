@@ -0,0 +1,85 @@
+use config::Config;
+use std::cell::Cell;
+use std::io::{Result, Error, ErrorKind};
+use std::rc::Rc;
+use std::time::Instant;
+
+/// This presents a source of processing time for operators.
+///
+/// Operators that act on processing time should take a clock rather than
+/// calling Instant::now themselves, so that the harness can decide where
+/// the time comes from, and so that tests can run on a simulated time.
+pub trait Clock {
+    /// Returns the current processing time in milliseconds.
+    fn now(&self) -> usize;
+}
+
+impl<C: Clock+?Sized> Clock for Box<C> {
+    fn now(&self) -> usize { (**self).now() }
+}
+
+/// A clock that returns the wall clock time elapsed since its creation.
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock{ start: Instant::now() }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> usize {
+        let elapsed = self.start.elapsed();
+        elapsed.as_secs() as usize * 1000 + elapsed.subsec_nanos() as usize / 1_000_000
+    }
+}
+
+/// A clock whose time is entirely under our control.
+///
+/// Every reading of the time advances the clock by the step, so that a
+/// dataflow can be run on a deterministic time. Clones share the same time,
+/// which allows the time to be set from outside of an operator.
+#[derive(Clone)]
+pub struct SimulatedClock {
+    time: Rc<Cell<usize>>,
+    step: usize,
+}
+
+impl SimulatedClock {
+    pub fn new(step: usize) -> Self {
+        SimulatedClock{ time: Rc::new(Cell::new(0)), step: step }
+    }
+
+    #[allow(dead_code)]
+    pub fn set(&self, time: usize) {
+        self.time.set(time);
+    }
+
+    #[allow(dead_code)]
+    pub fn advance(&self, duration: usize) {
+        self.time.set(self.time.get() + duration);
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&self) -> usize {
+        let time = self.time.get();
+        self.time.set(time + self.step);
+        time
+    }
+}
+
+/// Creates the processing time clock for a test run from the config.
+///
+/// The clock option can be either "system" to use the wall clock, or
+/// "simulated", in which case the clock advances by clock-step every time
+/// it is read.
+pub fn from_config(config: &Config) -> Result<Box<Clock>> {
+    match config.get_or("clock", "system").as_ref() {
+        "system" => Ok(Box::new(SystemClock::new())),
+        "simulated" => Ok(Box::new(SimulatedClock::new(config.get_as_or("clock-step", 1)))),
+        _ => Err(Error::new(ErrorKind::Other, "Unknown clock."))
+    }
+}
@@ -0,0 +1,185 @@
+use config::Config;
+use rand::Rng;
+use std::cmp::min;
+use std::io::{Result, Error, ErrorKind};
+
+/// The shape of a key distribution.
+#[derive(Clone, Debug, PartialEq)]
+enum Shape {
+    /// Every key is equally likely.
+    Uniform,
+    /// Key i is chosen proportionally to 1/(i+1)^s. Holds the CDF.
+    Zipf(Vec<f64>),
+    /// The first `hot` keys receive `probability` of all samples.
+    Hotspot { hot: usize, probability: f64 },
+    /// Like Hotspot, but the hot keys move on by `hot` keys every `period` epochs.
+    ShiftingHotspot { hot: usize, probability: f64, period: usize },
+}
+
+/// This struct samples key indices in [0, n) according to a configured shape.
+///
+/// It is used by the data generators to control the skew of the keys they
+/// produce, so that skew handling in the operators can be evaluated. Each
+/// generator maps the sampled indices onto its own keys.
+#[derive(Clone, Debug)]
+pub struct Distribution {
+    keys: usize,
+    shape: Shape,
+}
+
+impl Distribution {
+    pub fn uniform(keys: usize) -> Self {
+        Distribution{ keys: keys, shape: Shape::Uniform }
+    }
+
+    pub fn zipf(keys: usize, exponent: f64) -> Self {
+        let mut total = 0.0;
+        let mut cdf = Vec::with_capacity(keys);
+        for i in 0..keys {
+            total += 1.0 / ((i+1) as f64).powf(exponent);
+            cdf.push(total);
+        }
+        for c in cdf.iter_mut() { *c /= total; }
+        Distribution{ keys: keys, shape: Shape::Zipf(cdf) }
+    }
+
+    pub fn hotspot(keys: usize, hot: usize, probability: f64) -> Self {
+        Distribution{ keys: keys, shape: Shape::Hotspot{ hot: min(hot, keys), probability: probability } }
+    }
+
+    pub fn shifting_hotspot(keys: usize, hot: usize, probability: f64, period: usize) -> Self {
+        Distribution{ keys: keys, shape: Shape::ShiftingHotspot{ hot: min(hot, keys), probability: probability, period: period.max(1) } }
+    }
+
+    /// Creates a distribution over the given number of keys from the config.
+    ///
+    /// All options are prefixed with the given name, so that each generator
+    /// can configure each of its keys separately:
+    ///   PREFIX-distribution   One of uniform, zipf, hotspot, shifting-hotspot.
+    ///   PREFIX-skew           The Zipf exponent. Implies the zipf distribution.
+    ///   PREFIX-hot-keys       The number of hot keys.
+    ///   PREFIX-hot-probability  The probability of choosing a hot key.
+    ///   PREFIX-shift-period   The number of epochs after which the hot keys shift.
+    ///
+    /// Giving a skew along with any other distribution is an error, as it
+    /// would silently have no effect.
+    pub fn from_config(config: &Config, prefix: &str, keys: usize, default: &str) -> Result<Self> {
+        let option = |name: &str| format!("{}-{}", prefix, name);
+        let hot = config.get_as_or(&option("hot-keys"), (keys / 100).max(1));
+        let probability = config.get_as_or(&option("hot-probability"), 0.9);
+        let skew = config.get_as::<f64>(&option("skew"));
+        let distribution = match (config.get(&option("distribution")), skew) {
+            (Some(distribution), _) => distribution,
+            (None, Some(_)) => String::from("zipf"),
+            (None, None) => String::from(default),
+        };
+        if skew.is_some() && distribution != "zipf" {
+            return Err(Error::new(ErrorKind::Other, format!("The {} skew only applies to the zipf distribution.", prefix)));
+        }
+        match distribution.as_ref() {
+            "uniform" => Ok(Self::uniform(keys)),
+            "zipf" => Ok(Self::zipf(keys, skew.unwrap_or(1.0))),
+            "hotspot" => Ok(Self::hotspot(keys, hot, probability)),
+            "shifting-hotspot" => Ok(Self::shifting_hotspot(keys, hot, probability, config.get_as_or(&option("shift-period"), 10))),
+            _ => Err(Error::new(ErrorKind::Other, format!("Unknown {} distribution.", prefix)))
+        }
+    }
+
+    /// Returns the number of keys this distribution samples from.
+    #[allow(dead_code)]
+    pub fn keys(&self) -> usize {
+        self.keys
+    }
+
+    /// Samples a key index. The epoch is used to shift hotspots over time.
+    pub fn sample<R: Rng>(&self, rng: &mut R, epoch: usize) -> usize {
+        if self.keys <= 1 { return 0; }
+        match self.shape {
+            Shape::Uniform => rng.gen_range(0, self.keys),
+            Shape::Zipf(ref cdf) => {
+                let u = rng.gen::<f64>();
+                match cdf.binary_search_by(|c| c.partial_cmp(&u).unwrap()) {
+                    Ok(i) => i,
+                    Err(i) => min(i, self.keys-1)
+                }
+            },
+            Shape::Hotspot{ hot, probability } => {
+                Self::sample_hotspot(rng, self.keys, hot, probability)
+            },
+            Shape::ShiftingHotspot{ hot, probability, period } => {
+                let offset = (epoch / period) * hot;
+                (Self::sample_hotspot(rng, self.keys, hot, probability) + offset) % self.keys
+            },
+        }
+    }
+
+    fn sample_hotspot<R: Rng>(rng: &mut R, keys: usize, hot: usize, probability: f64) -> usize {
+        if hot == 0 || hot == keys {
+            rng.gen_range(0, keys)
+        } else if rng.gen::<f64>() < probability {
+            rng.gen_range(0, hot)
+        } else {
+            rng.gen_range(hot, keys)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{StdRng, SeedableRng};
+
+    fn histogram(dist: &Distribution, epoch: usize) -> Vec<usize> {
+        let mut rng = StdRng::from_seed(&[1, 2, 3]);
+        let mut counts = vec![0; dist.keys()];
+        for _ in 0..10000 {
+            counts[dist.sample(&mut rng, epoch)] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn uniform() {
+        let counts = histogram(&Distribution::uniform(10), 0);
+        assert!(counts.iter().all(|&c| 800 < c && c < 1200));
+    }
+
+    #[test]
+    fn zipf() {
+        let counts = histogram(&Distribution::zipf(10, 1.0), 0);
+        // With an exponent of 1 the first key gets about a third of all samples.
+        assert!(3000 < counts[0] && counts[0] < 3800);
+        assert!(counts[1] < counts[0] && counts[9] < counts[1]);
+        // With an exponent of 0 it degrades to a uniform distribution.
+        let counts = histogram(&Distribution::zipf(10, 0.0), 0);
+        assert!(counts.iter().all(|&c| 800 < c && c < 1200));
+    }
+
+    #[test]
+    fn from_config() {
+        let mut config = Config::new();
+        config.insert("ip-skew", String::from("0.0"));
+        // A skew alone selects the zipf distribution.
+        let dist = Distribution::from_config(&config, "ip", 10, "uniform").unwrap();
+        assert_eq!(dist.shape, Distribution::zipf(10, 0.0).shape);
+        config.insert("ip-distribution", String::from("hotspot"));
+        assert!(Distribution::from_config(&config, "ip", 10, "uniform").is_err());
+        config.insert("ip-distribution", String::from("zipf"));
+        assert!(Distribution::from_config(&config, "ip", 10, "uniform").is_ok());
+    }
+
+    #[test]
+    fn hotspot() {
+        let counts = histogram(&Distribution::hotspot(100, 2, 0.9), 0);
+        assert!(8500 < counts[0] + counts[1]);
+    }
+
+    #[test]
+    fn shifting_hotspot() {
+        let dist = Distribution::shifting_hotspot(100, 2, 0.9, 10);
+        let early = histogram(&dist, 5);
+        let late = histogram(&dist, 15);
+        assert!(8500 < early[0] + early[1]);
+        assert!(8500 < late[2] + late[3]);
+    }
+}
@@ -2,6 +2,8 @@ use config::Config;
 use std::io::{self, Result, Error, ErrorKind, Write, Stdout, Stdin, Lines, BufReader, BufRead, BufWriter};
 use std::error::Error as StdError;
 use std::fs::File;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
 use timely::progress::timestamp::Timestamp;
 use timely::Data;
 //use kafkaesque;
@@ -24,6 +26,47 @@ where D: Default {
     } else { Err(e) })
 }
 
+/// Pulls everything out of the source as quickly as possible.
+///
+/// Returns the number of events produced and the time it took to do so.
+/// This is used to check whether a generator can keep up with a rate.
+pub fn exhaust<T, D, S: EventSource<T, D>>(source: &mut S) -> Result<(usize, Duration)> {
+    let start = Instant::now();
+    let mut count = 0;
+    loop {
+        match source.next() {
+            Ok((_, d)) => count += d.len(),
+            Err(e) => if is_out_of_data(&e) { break; } else { return Err(e); }
+        }
+    }
+    Ok((count, start.elapsed()))
+}
+
+/// Measures how many events per second each of the generators can produce.
+///
+/// Every generator is exhausted on a thread of its own, as they would be
+/// on the workers during a test run. The throughput of each thread and of
+/// all threads together is printed.
+pub fn benchmark_generators<T, D, S>(generators: Vec<S>) -> Result<()>
+where S: EventSource<T, D>+Send+'static, T: 'static, D: 'static {
+    let mut threads: Vec<JoinHandle<Result<(usize, Duration)>>> = Vec::new();
+    for mut generator in generators {
+        threads.push(thread::spawn(move || exhaust(&mut generator)));
+    }
+    let fsecs = |d: Duration| d.as_secs() as f64 + d.subsec_nanos() as f64 / 1_000_000_000.0;
+    let mut total = 0;
+    let mut longest = Duration::new(0, 0);
+    println!("Thread     Events     Seconds    Events/s   ");
+    for (p, t) in threads.drain(..).enumerate() {
+        let (count, duration) = t.join().unwrap()?;
+        println!("{:10} {:10} {:10.5} {:10.2}", p, count, fsecs(duration), count as f64 / fsecs(duration));
+        total += count;
+        longest = longest.max(duration);
+    }
+    println!("{:10} {:10} {:10.5} {:10.2}", "Total", total, fsecs(longest), total as f64 / fsecs(longest));
+    Ok(())
+}
+
 pub trait EventSource<T, D> {
     fn next(&mut self) -> Result<(T, Vec<D>)>;
 }
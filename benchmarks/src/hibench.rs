@@ -1,6 +1,7 @@
 use config::Config;
-use operators::{Window, RollingCount, Reduce};
-use rand::{self, Rng};
+use distribution::Distribution;
+use operators::{RollingCount, Reduce};
+use rand::{self, Rng, StdRng, SeedableRng};
 use std::cmp::min;
 use std::fs::File;
 use std::fs;
@@ -60,14 +61,56 @@ impl FromData<usize> for Event{
 // 0    35.143.225.164,nbizrgdziebsaecsecujfjcqtvnpcnxxwiopmddorcxnlijdizgoi,1996-05-31,0.8792629,Mozilla/5.0 (Windows; U; Windows NT 5.2) AppleWebKit/525.13 (KHTML like Gecko) Chrome/0.2.149.27 Safari/525.13,PRT,PRT-PT,fraternally,8
 // 0    34.57.45.175,nbizrgdziebtsaecsecujfjcqtvnpcnxxwiopmddorcxnlijdizgoi,2001-06-29,0.14202267,Mozilla/4.0 (compatible; MSIE 6.0; Windows NT 5.1),DOM,DOM-ES,Gaborone's,7
 
-fn random_ip() -> String {
-    let mut rng = rand::thread_rng();
+fn random_ip<R: Rng>(rng: &mut R) -> String {
     format!("{}.{}.{}.{}", rng.gen_range(0, 255), rng.gen_range(0, 255), rng.gen_range(0, 255), rng.gen_range(0, 255))
 }
 
+/// Generates the IP table that the IP distribution picks from.
+///
+/// Like the vocabulary, the IPs are derived from a fixed seed, so that a
+/// key index means the same IP on every worker and the skew is shared.
+fn ip_table(size: usize) -> Vec<String> {
+    let mut rng = StdRng::from_seed(&[0x1F]);
+    (0..size).map(|_| random_ip(&mut rng)).collect()
+}
+
 fn random_date() -> String {
     let mut rng = rand::thread_rng();
-    format!("{}-{}-{}", rng.gen_range(1990, 2010), rng.gen_range(1, 12), rng.gen_range(0, 31))
+    format!("{}-{:02}-{:02}", rng.gen_range(1990, 2010), rng.gen_range(1, 13), rng.gen_range(1, 29))
+}
+
+/// User agents as they appear in the HiBench uservisits data set, most
+/// common first, so that a skewed distribution favours the front.
+const USER_AGENTS: [&str; 8] = [
+    "Mozilla/4.0 (compatible; MSIE 6.0; Windows NT 5.1)",
+    "Mozilla/4.0 (compatible; MSIE 7.0; Windows NT 6.0)",
+    "Mozilla/5.0 (Windows; U; Windows NT 5.2) AppleWebKit/525.13 (KHTML like Gecko) Chrome/0.2.149.27 Safari/525.13",
+    "Mozilla/5.0 (iPhone; U; CPU like Mac OS X)AppleWebKit/420.1 (KHTML like Gecko) Version/3.0 Mobile/4A93Safari/419.3",
+    "Mozilla/5.0 (Windows; U; Windows NT 5.1; en-US; rv:1.8.1.13) Gecko/20080311 Firefox/2.0.0.13",
+    "Mozilla/5.0 (Macintosh; U; Intel Mac OS X 10_5_2; en-us) AppleWebKit/525.13 (KHTML like Gecko) Version/3.1 Safari/525.13",
+    "Opera/9.25 (Windows NT 5.1; U; en)",
+    "Googlebot/2.1 (+http://www.google.com/bot.html)",
+];
+
+/// Country and language code pairs as they appear in the HiBench uservisits
+/// data set, most common first.
+const COUNTRIES: [(&str, &str); 16] = [
+    ("USA", "USA-EN"), ("CHN", "CHN-ZH"), ("IND", "IND-HI"), ("BRA", "BRA-PT"),
+    ("DEU", "DEU-DE"), ("GBR", "GBR-EN"), ("FRA", "FRA-FR"), ("JPN", "JPN-JA"),
+    ("RUS", "RUS-RU"), ("MEX", "MEX-ES"), ("ESP", "ESP-ES"), ("ITA", "ITA-IT"),
+    ("DOM", "DOM-ES"), ("YEM", "YEM-AR"), ("PRT", "PRT-PT"), ("CAN", "CAN-FR"),
+];
+
+/// Generates the search word vocabulary.
+///
+/// The words are derived from a fixed seed so that every partition and
+/// every run uses the same vocabulary.
+fn vocabulary(size: usize) -> Vec<String> {
+    let mut rng = StdRng::from_seed(&[0x5EED]);
+    (0..size).map(|_| {
+        let len = rng.gen_range(3, 13);
+        rng.gen_ascii_chars().filter(|c| c.is_alphabetic()).take(len).collect::<String>().to_lowercase()
+    }).collect()
 }
 
 struct Identity {
@@ -87,7 +130,7 @@ impl TestImpl for Identity {
     fn name(&self) -> &str { "HiBench Identity" }
 
     fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config))))?,
+        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config)?)))?,
             Drain::from_config(config)?))
     }
 
@@ -119,7 +162,7 @@ impl TestImpl for Repartition {
     fn name(&self) -> &str { "HiBench Repartition" }
     
     fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config))))?,
+        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config)?)))?,
             Drain::from_config(config)?))
     }
     
@@ -162,7 +205,7 @@ impl TestImpl for Wordcount {
     fn name(&self) -> &str { "HiBench Wordcount" }
 
     fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config))))?, 
+        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config)?)))?, 
             Drain::from_config(config)?))
     }
 
@@ -195,7 +238,7 @@ impl TestImpl for Fixwindow {
     fn name(&self) -> &str { "HiBench Fixwindow" }
 
     fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config))))?, 
+        Ok((Source::from_config(config, Source::new(Box::new(HiBenchGenerator::new(config)?)))?, 
             Drain::from_config(config)?))
     }
 
@@ -204,9 +247,9 @@ impl TestImpl for Fixwindow {
         let window_size = config.get_as_or("window-size", 10) as usize;
         stream
             .map(|e| (e.ip(), e.time))
-            .tumbling_window(window_size)
-            .reduce_by(|&(ref ip, _)| ip.clone(),
-                       (0, 0), |(_, t), (m, c)| (min(m, t), c+1))
+            .window_reduce_by(window_size, window_size, |&(ref ip, _)| ip.clone(),
+                              (0, 0), |(_, t), (m, c)| (min(m, t), c+1),
+                              |(m1, c1), (m2, c2)| (min(m1, m2), c1+c2))
     }
 }
 
@@ -216,27 +259,44 @@ impl<T: Timestamp> FromData<T> for (String, (usize, u32)) {
     }
 }
 
+/// Generates records in the format of the HiBench uservisits data set.
+///
+/// IPs, words, agents and countries are each picked through a configurable
+/// distribution. The agent and country tables are short excerpts of the
+/// real data set, and the dates, revenues and visit durations are drawn
+/// uniformly, so these fields only approximate HiBench's distributions.
 #[derive(Clone)]
 pub struct HiBenchGenerator {
     ips: Vec<String>,
+    ip_distribution: Distribution,
+    words: Vec<String>,
+    word_distribution: Distribution,
+    agent_distribution: Distribution,
+    country_distribution: Distribution,
     count: usize,
     epoch: usize,
     max: usize,
 }
 
 impl HiBenchGenerator {
-    fn new(config: &Config) -> Self {
+    fn new(config: &Config) -> Result<Self> {
         let partitions = config.get_as_or("threads", 1);
         let seconds = config.get_as_or("seconds", 60);
         let events_per_second = config.get_as_or("events-per-second", 100_000);
         let ips = config.get_as_or("ips", 100);
+        let words = config.get_as_or("words", 1000);
         
-        HiBenchGenerator {
-            ips: (0..ips).map(|_| random_ip()).collect(),
+        Ok(HiBenchGenerator {
+            ips: ip_table(ips),
+            ip_distribution: Distribution::from_config(config, "ip", ips, "uniform")?,
+            words: vocabulary(words),
+            word_distribution: Distribution::from_config(config, "word", words, "zipf")?,
+            agent_distribution: Distribution::from_config(config, "agent", USER_AGENTS.len(), "zipf")?,
+            country_distribution: Distribution::from_config(config, "country", COUNTRIES.len(), "zipf")?,
             count: events_per_second/partitions,
             epoch: 0,
             max: seconds,
-        }
+        })
     }
 }
 
@@ -247,15 +307,16 @@ impl EventSource<usize, Event> for HiBenchGenerator {
             let mut data = Vec::with_capacity(self.count);
             
             for _ in 0..self.count {
-                let ip = rng.choose(&self.ips).unwrap().clone();
+                let ip = &self.ips[self.ip_distribution.sample(&mut rng, self.epoch)];
                 let session: String = rng.gen_ascii_chars().take(54).collect();
                 let date = random_date();
-                let float = 0.0;
-                let agent = "Mozilla/4.0 (compatible; MSIE 6.0; Windows NT 5.1)";
-                let s = "DOM";
-                let subs = "DOM-ES";
-                let word = "snow";
-                let int = 6;
+                let float = rng.gen::<f32>();
+                let agent = USER_AGENTS[self.agent_distribution.sample(&mut rng, self.epoch)];
+                let (s, subs) = COUNTRIES[self.country_distribution.sample(&mut rng, self.epoch)];
+                let word = &self.words[self.word_distribution.sample(&mut rng, self.epoch)];
+                // HiBench draws the visit duration from a small range; this
+                // approximates it with a uniform pick.
+                let int = rng.gen_range(1, 11);
                 data.push(Event{
                     time: self.epoch,
                     data: format!("{},{},{},{:.8},{},{},{},{},{}",
@@ -291,7 +352,11 @@ impl Benchmark for HiBench {
         println!("Generating {} events/s for {}s over {} partitions for {} ips.",
                  events_per_second, seconds, partitions, ips);
 
-        let generator = HiBenchGenerator::new(config);
+        let generator = HiBenchGenerator::new(config)?;
+        if config.get_as_or("benchmark", false) {
+            return endpoint::benchmark_generators(vec![generator; partitions]);
+        }
+
         let mut threads: Vec<JoinHandle<Result<()>>> = Vec::new();
         for p in 0..partitions {
             let mut file = File::create(format!("{}/events-{}.csv", &data_dir, p))?;
@@ -318,3 +383,44 @@ impl Benchmark for HiBench {
              Box::new(Fixwindow::new())]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_ips() {
+        let mut config = Config::new();
+        config.insert("ips", String::from("50"));
+        let a = HiBenchGenerator::new(&config).unwrap();
+        let b = HiBenchGenerator::new(&config).unwrap();
+        assert_eq!(a.ips.len(), 50);
+        assert_eq!(a.ips, b.ips);
+    }
+
+    #[test]
+    fn parseable() {
+        let mut config = Config::new();
+        config.insert("events-per-second", String::from("100"));
+        let mut generator = HiBenchGenerator::new(&config).unwrap();
+        generator.next().unwrap();
+        let (epoch, events) = generator.next().unwrap();
+        assert_eq!(epoch, 1);
+        assert_eq!(events.len(), 100);
+        for event in events {
+            // The events go through the same format as the data files.
+            let (time, parsed): (usize, Event) = event.from_data(&epoch).to_data().unwrap();
+            assert_eq!(time, 1);
+            assert_eq!(parsed.ip(), event.ip());
+            let fields: Vec<&str> = parsed.data.split(',').collect();
+            assert_eq!(fields.len(), 9);
+            assert!(generator.ips.iter().any(|ip| ip == fields[0]));
+            assert_eq!(fields[1].len(), 54);
+            assert!(USER_AGENTS.contains(&fields[4]));
+            assert!(COUNTRIES.contains(&(fields[5], fields[6])));
+            assert!(generator.words.iter().any(|w| w == fields[7]));
+            let duration = usize::from_str(fields[8]).unwrap();
+            assert!(1 <= duration && duration <= 10);
+        }
+    }
+}
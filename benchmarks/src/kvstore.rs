@@ -0,0 +1,288 @@
+use config::Config;
+use std::collections::HashMap;
+use std::io::{Result, Error, ErrorKind, BufRead, BufReader, BufWriter, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+/// This presents the interface to an external key-value store.
+///
+/// It is kept deliberately minimal so that it can be implemented
+/// for anything from a plain in-memory map up to a remote Redis.
+pub trait KVClient {
+    /// Returns the value stored under the key, if any.
+    fn get(&mut self, key: &str) -> Result<Option<String>>;
+
+    /// Stores the value under the key, overwriting any previous value.
+    fn set(&mut self, key: &str, value: &str) -> Result<()>;
+}
+
+/// A single value of the Redis serialisation protocol (RESP).
+#[derive(Debug, PartialEq, Clone)]
+pub enum Reply {
+    Status(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<String>),
+    Array(Vec<Reply>),
+}
+
+fn protocol_error<D>(message: &str) -> Result<D> {
+    Err(Error::new(ErrorKind::InvalidData, message))
+}
+
+/// Reads one RESP value from the reader.
+fn read_reply<R: BufRead>(reader: &mut R) -> Result<Reply> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(Error::new(ErrorKind::UnexpectedEof, "Connection closed."));
+    }
+    let line = line.trim_right();
+    if line.len() == 0 { return protocol_error("Empty reply line."); }
+    let (kind, rest) = line.split_at(1);
+    let number = || rest.parse::<i64>().map_err(|_| Error::new(ErrorKind::InvalidData, "Invalid length."));
+    match kind {
+        "+" => Ok(Reply::Status(String::from(rest))),
+        "-" => Ok(Reply::Error(String::from(rest))),
+        ":" => Ok(Reply::Integer(number()?)),
+        "$" => {
+            let len = number()?;
+            if len < 0 { return Ok(Reply::Bulk(None)); }
+            // Include the trailing CRLF.
+            let mut buf = vec![0; len as usize + 2];
+            reader.read_exact(&mut buf)?;
+            buf.truncate(len as usize);
+            String::from_utf8(buf)
+                .map(|s| Reply::Bulk(Some(s)))
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Bulk string is not UTF-8."))
+        },
+        "*" => {
+            let len = number()?;
+            let mut values = Vec::with_capacity(len.max(0) as usize);
+            for _ in 0..len {
+                values.push(read_reply(reader)?);
+            }
+            Ok(Reply::Array(values))
+        },
+        _ => protocol_error("Unknown reply type.")
+    }
+}
+
+/// Writes one RESP value to the writer.
+fn write_reply<W: Write>(writer: &mut W, reply: &Reply) -> Result<()> {
+    match reply {
+        &Reply::Status(ref s) => write!(writer, "+{}\r\n", s),
+        &Reply::Error(ref s) => write!(writer, "-{}\r\n", s),
+        &Reply::Integer(i) => write!(writer, ":{}\r\n", i),
+        &Reply::Bulk(None) => write!(writer, "$-1\r\n"),
+        &Reply::Bulk(Some(ref s)) => write!(writer, "${}\r\n{}\r\n", s.len(), s),
+        &Reply::Array(ref values) => {
+            write!(writer, "*{}\r\n", values.len())?;
+            for value in values {
+                write_reply(writer, value)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A client that speaks the Redis protocol over TCP.
+///
+/// This works against a real Redis server as well as against the
+/// LocalRedis stand-in below.
+pub struct RedisClient {
+    reader: BufReader<TcpStream>,
+    writer: BufWriter<TcpStream>,
+}
+
+impl RedisClient {
+    pub fn connect(address: &str) -> Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        stream.set_nodelay(true)?;
+        Ok(RedisClient {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: BufWriter::new(stream),
+        })
+    }
+
+    /// Sends a command and waits for its reply.
+    ///
+    /// Error replies from the server are turned into Err results.
+    pub fn command(&mut self, args: &[&str]) -> Result<Reply> {
+        let command = Reply::Array(args.iter().map(|a| Reply::Bulk(Some(String::from(*a)))).collect());
+        write_reply(&mut self.writer, &command)?;
+        self.writer.flush()?;
+        match read_reply(&mut self.reader)? {
+            Reply::Error(e) => Err(Error::new(ErrorKind::Other, e)),
+            reply => Ok(reply)
+        }
+    }
+}
+
+impl KVClient for RedisClient {
+    fn get(&mut self, key: &str) -> Result<Option<String>> {
+        match self.command(&["GET", key])? {
+            Reply::Bulk(value) => Ok(value),
+            _ => protocol_error("Unexpected reply to GET.")
+        }
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        self.command(&["SET", key, value]).map(|_| ())
+    }
+}
+
+/// A client that directly accesses a map shared within the process.
+///
+/// This is useful as a baseline to separate the cost of the protocol
+/// and network round-trips from the cost of the lookups themselves.
+#[derive(Clone)]
+pub struct MemoryClient {
+    map: Arc<RwLock<HashMap<String, String>>>,
+}
+
+impl MemoryClient {
+    pub fn new() -> Self {
+        MemoryClient{ map: Arc::new(RwLock::new(HashMap::new())) }
+    }
+}
+
+impl KVClient for MemoryClient {
+    fn get(&mut self, key: &str) -> Result<Option<String>> {
+        Ok(self.map.read().unwrap().get(key).cloned())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        self.map.write().unwrap().insert(String::from(key), String::from(value));
+        Ok(())
+    }
+}
+
+/// An in-process stand-in for a Redis server.
+///
+/// It listens on a random local port and understands enough of the
+/// protocol (PING, GET, SET, DEL) to serve the benchmarks, so that the
+/// external store can be measured without a Redis installation. The
+/// server threads are detached and live until the process exits.
+pub struct LocalRedis {
+    address: String,
+}
+
+impl LocalRedis {
+    pub fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = format!("{}", listener.local_addr()?);
+        let data = Arc::new(Mutex::new(HashMap::new()));
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if let Ok(stream) = stream {
+                    let data = data.clone();
+                    thread::spawn(move || { let _ = serve(stream, data); });
+                }
+            }
+        });
+        Ok(LocalRedis{ address: address })
+    }
+
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+}
+
+/// Handles a single client connection until it is closed.
+fn serve(stream: TcpStream, data: Arc<Mutex<HashMap<String, String>>>) -> Result<()> {
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = BufWriter::new(stream);
+    loop {
+        let args = match read_reply(&mut reader) {
+            Ok(Reply::Array(args)) => args,
+            Ok(_) => return protocol_error("Expected a command array."),
+            Err(ref e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+            Err(e) => return Err(e)
+        };
+        let args: Vec<String> = args.into_iter().filter_map(|a| match a {
+            Reply::Bulk(Some(s)) => Some(s),
+            _ => None
+        }).collect();
+        let reply = match (args.get(0).map(|s| s.to_uppercase()), args.len()) {
+            (Some(ref c), 1) if c == "PING" => Reply::Status(String::from("PONG")),
+            (Some(ref c), 2) if c == "GET" => Reply::Bulk(data.lock().unwrap().get(&args[1]).cloned()),
+            (Some(ref c), 3) if c == "SET" => {
+                data.lock().unwrap().insert(args[1].clone(), args[2].clone());
+                Reply::Status(String::from("OK"))
+            },
+            (Some(ref c), n) if c == "DEL" && 1 < n => {
+                let mut data = data.lock().unwrap();
+                Reply::Integer(args[1..].iter().filter(|k| data.remove(*k).is_some()).count() as i64)
+            },
+            (Some(c), _) => Reply::Error(format!("ERR unknown command or wrong arguments for '{}'", c)),
+            (None, _) => Reply::Error(String::from("ERR empty command")),
+        };
+        write_reply(&mut writer, &reply)?;
+        writer.flush()?;
+    }
+}
+
+/// This describes where the key-value store for a test run is located.
+///
+/// A store is created once per process and then handed out to each
+/// worker, which can connect its own clients to it.
+#[derive(Clone)]
+pub enum Store {
+    Memory(MemoryClient),
+    Redis(String),
+}
+
+impl Store {
+    /// Selects the store according to the kv-store option.
+    ///
+    /// For the "local" mode this starts a LocalRedis stand-in server.
+    pub fn from_config(config: &Config) -> Result<Self> {
+        match config.get_or("kv-store", "local").as_ref() {
+            "memory" => Ok(Store::Memory(MemoryClient::new())),
+            "local" => Ok(Store::Redis(String::from(LocalRedis::start()?.address()))),
+            "redis" => Ok(Store::Redis(config.get_or("kv-server", "localhost:6379"))),
+            _ => Err(Error::new(ErrorKind::Other, "Unknown key-value store."))
+        }
+    }
+
+    /// Returns a fresh client connected to this store.
+    pub fn connect(&self) -> Result<Box<KVClient+Send>> {
+        match self {
+            &Store::Memory(ref client) => Ok(Box::new(client.clone())),
+            &Store::Redis(ref address) => Ok(Box::new(RedisClient::connect(address)?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_redis() {
+        let server = LocalRedis::start().unwrap();
+        let mut client = RedisClient::connect(server.address()).unwrap();
+        assert_eq!(client.command(&["PING"]).unwrap(), Reply::Status(String::from("PONG")));
+        assert_eq!(client.get("a").unwrap(), None);
+        client.set("a", "1").unwrap();
+        client.set("b", "two words").unwrap();
+        // A second connection must see the same data.
+        let mut other = RedisClient::connect(server.address()).unwrap();
+        assert_eq!(other.get("a").unwrap(), Some(String::from("1")));
+        assert_eq!(other.get("b").unwrap(), Some(String::from("two words")));
+        assert_eq!(other.command(&["DEL", "a", "c"]).unwrap(), Reply::Integer(1));
+        assert_eq!(client.get("a").unwrap(), None);
+        assert!(client.command(&["FOO"]).is_err());
+    }
+
+    #[test]
+    fn memory_client() {
+        let mut client = MemoryClient::new();
+        let mut other = client.clone();
+        client.set("a", "1").unwrap();
+        assert_eq!(other.get("a").unwrap(), Some(String::from("1")));
+        assert_eq!(other.get("b").unwrap(), None);
+    }
+}
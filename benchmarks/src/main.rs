@@ -16,8 +16,11 @@ extern crate num;
 extern crate fnv;
 mod operators;
 mod config;
+mod clock;
 mod statistics;
 mod endpoint;
+mod kvstore;
+mod distribution;
 mod test;
 mod hibench;
 mod ysb;
@@ -116,7 +119,7 @@ Usage: MODE [OPTIONS]
 The following modes are available:
 
 test                       Run the benchmarks.
-generate                   Generate data to files.
+generate                   Generate data to files, or measure the generators with --benchmark.
 help                       Show this help document.
 
 The following options are available:
@@ -139,6 +142,13 @@ The following options are available:
                              Default: test-dependent
   --window-slide NUM       The slide of the windows in epochs (usually seconds).
                              Default: test-dependent
+  --clock CLOCK            The processing time clock. Can be one of:
+                             system, simulated
+                             Default: system
+  --clock-step NUM         How many milliseconds the simulated clock advances per reading.
+                             Default: 1
+  --allowed-lateness NUM   How many epochs to keep updating event-time windows after they fired.
+                             Default: 0
   --input MODE             The input generation mode. Can be one of:
                              null, console, file, generated
                              Default: generated
@@ -158,16 +168,89 @@ The following options are available:
                              Default: data/
   --threads NUM            The number of dataset partitions to create.
                              Default: 10
+  --benchmark BOOL         Only measure how fast events are generated per thread, without writing them.
+                             Default: false
+  --pregenerate BOOL       Generate all epochs into memory before running a test, so that
+                           the measured latencies exclude the generation.
+                             Default: false
   --seconds NUM            How many seconds to generate events for.
                              Default: 60
   --events-per-second NUM  How many events to produce per second.
                              Default: 100000
   --ips NUM                (HIB) How many IPs to generate.
                              Default: 100
+  --words NUM              (HIB) How many distinct search words to generate.
+                             Default: 1000
+  --KEY-distribution DIST  How to distribute the choice of keys for generated events.
+                             KEY can be one of:
+                               ip, word, agent, country (HIB), ad (YSB), auction, bidder (NEX)
+                             DIST can be one of:
+                               uniform, zipf, hotspot, shifting-hotspot
+                             Default: zipf for word, agent and country, uniform for ip and ad,
+                             hot ratios for NEX
+  --KEY-skew FLOAT         The exponent of the zipf distribution. Selects zipf if
+                             no distribution is given, and is rejected for others.
+                             Default: 1.0
+  --KEY-hot-keys NUM       The number of hot keys in the hotspot distributions.
+                             Default: 1% of the keys
+  --KEY-hot-probability FLOAT  The probability of choosing a hot key.
+                             Default: 0.9
+  --KEY-shift-period NUM   How many epochs until the hot keys shift.
+                             Default: 10
   --campaigns NUM          (YSB) How many campaign IDs to generate.
                              Default: 100
   --ads NUM                (YSB) How many ads per campaign to generate.
                              Default: 10
+  --campaign-seed NUM      (YSB) The seed from which the campaign table is generated.
+                             Default: 51966
+  --campaigns-file FILE    (YSB) A campaigns.json file to load the campaign table from.
+                             Default: DATA-DIR/ysb/campaigns.json if the input is file, otherwise none
+  --campaign-updates NUM   (YSB) How many ads to reassign to another campaign per epoch in the join test.
+                             Default: 0
+  --campaign-additions NUM (YSB) How many campaigns to add per epoch in the join test.
+                             Default: 0
+  --kv-store MODE          (YSB) The key-value store to use for the store test. Can be one of:
+                             memory, local, redis
+                             Default: local
+  --kv-server HOST         (YSB) The address of the Redis server to use with the redis store.
+                             Default: localhost:6379
+  --shards NUM             (NEX) Over how many shards to log bids in Query 10.
+                             Default: 10
+  --pane-size NUM          (NEX) After how many bids a shard's window is written early in Query 10.
+                             Default: 1000
+  --log-dir DIR            (NEX) The directory to which Query 10 writes its panes.
+                             Default: DATA-DIR/nexmark/query10
+  --late-bids POLICY       (NEX) What to do with bids that arrive after their auction closed in queries 4, 6 and 9.
+                             Can be one of: drop, divert
+                             Default: drop
+  --closed-auction-retention NUM  (NEX) For how many epochs closed auctions are remembered to recognise late bids.
+                             Default: 60
+  --orphan-bid-retention NUM  (NEX) For how many epochs bids are kept whose auction has not arrived.
+                             Default: 60
+  --join-ttl NUM           (NEX) For how many epochs the join of Query 3 keeps records.
+                             Default: forever
+  --join-max-per-key NUM   (NEX) How many persons per id the join of Query 3 keeps.
+                             Default: unlimited
+  --join-capacity NUM      (NEX) How many person ids the join of Query 3 keeps, dropping the least recently used.
+                             Default: unlimited
+  --join-state-log BOOL    (NEX) Whether to print the number of records in the join state of Query 3
+                             on every worker after each epoch.
+                             Default: false
+  --fanout NUM             (NEX) How many workers are combined per level of the tree in the scalable Query 5.
+                             Default: 4
+  --side-input-size NUM    (NEX) How many entries the side input of Query 13 has.
+                             Default: 10000
+  --top-bids NUM           (NEX) How many bids per auction Query 19 keeps.
+                             Default: 10
+  --state-ttl NUM          (NEX) For how many epochs after their latest bid queries 18 and 19 keep
+                             the state of a bidder or auction. Queries 15 to 17 drop a day once it ended.
+                             Default: forever
+  --channels NUM           (NEX) How many channels other than the hot ones bids come from.
+                             Default: 10000
+  --hot-channel-ratio NUM  (NEX) One in this many bids comes from a channel that is not hot.
+                             Default: 2
+  --bid-extra-size NUM     (NEX) The maximal length of the extra field of bids.
+                             Default: 32
   --rate-shape SHAPE       (NEX) In what shape to generate delays between events. Can be one of:
                              SINE/SQUARE
                              Default: SINE
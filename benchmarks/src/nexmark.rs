@@ -1,31 +1,35 @@
 use serde_json;
 use abomonation::Abomonation;
+use clock;
 use config::Config;
-use endpoint::{self, Source, Drain, ToData, FromData, EventSource};
-use operators::{Window, Reduce, Join, FilterMap, Session, Partition};
+use distribution::Distribution;
+use endpoint::{self, Source, Drain, ToData, FromData, EventSource, EventDrain};
+use operators::{Window, Reduce, Join, JoinRetention, FilterMap, Session, LastN, TopK, IntegerTimestamp};
 use rand::{Rng, StdRng, SeedableRng};
 use std::char::from_u32;
 use std::cmp::{max, min};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::f64::consts::PI;
+use std::hash::{Hash, Hasher};
 use std::fs::File;
 use std::fs;
-use std::time::Instant;
 use std::thread::{self, JoinHandle};
-use std::io::{Result, Write};
+use std::io::{Result, Error, ErrorKind, Write};
 use test::{Test, TestImpl, Benchmark};
-use timely::dataflow::Stream;
-use timely::dataflow::operators::{Filter, Map, Binary};
+use timely::Data;
+use timely::dataflow::{Stream, Scope};
+use timely::dataflow::operators::{Filter, Map, Unary, Inspect, Concat};
 use timely::dataflow::scopes::{Root, Child};
-use timely::progress::timestamp::{Timestamp, RootTimestamp};
+use timely::progress::timestamp::Timestamp;
 use timely_communication::allocator::Generic;
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::channels::pact::{Pipeline, Exchange};
 
 type Id = usize;
 type Date = usize;
 
 const MIN_STRING_LENGTH: usize = 3;
 const BASE_TIME: usize = 1436918400_000;
+const HOT_CHANNELS: [&str; 4] = ["Google", "Facebook", "Baidu", "Apple"];
 
 fn split_string_arg(string: String) -> Vec<String> {
     string.split(",").map(String::from).collect::<Vec<String>>()
@@ -50,6 +54,15 @@ impl NEXMarkRng for StdRng {
     }
 }
 
+/// Returns the URL of the given channel, which is the same on every call.
+///
+/// This is expensive, so the generator computes all URLs up front.
+fn channel_url(channel: usize) -> String {
+    let mut rng = StdRng::from_seed(&[0x4E58, channel]);
+    let mut dir = || rng.gen_ascii_chars().take(5).collect::<String>();
+    format!("https://www.nexmark.com/{}/{}/{}/item.htm?query=1", dir(), dir(), dir())
+}
+
 #[derive(PartialEq)]
 enum RateShape {
     Square,
@@ -90,10 +103,18 @@ struct NEXMarkConfig {
     us_cities: Vec<String>,
     first_names: Vec<String>,
     last_names: Vec<String>,
+    channels: usize,
+    hot_channel_ratio: usize,
+    // The URLs of the hot channels followed by those of the others.
+    channel_urls: Vec<String>,
+    bid_extra_size: usize,
+    // If set, these replace the hot ratios for choosing a bid's auction and bidder.
+    auction_distribution: Option<Distribution>,
+    bidder_distribution: Option<Distribution>,
 }
 
 impl NEXMarkConfig {
-    fn new(config: &Config) -> Self{
+    fn new(config: &Config) -> Result<Self>{
         let active_people = config.get_as_or("active-people", 1000);
         let in_flight_auctions = config.get_as_or("in-flight-auctions", 100);
         let out_of_order_group_size = config.get_as_or("out-of-order-group-size", 1);
@@ -121,22 +142,35 @@ impl NEXMarkConfig {
         let us_cities = split_string_arg(config.get_or("us-cities", "phoenix,los angeles,san francisco,boise,portland,bend,redmond,seattle,kent,cheyenne"));
         let first_names = split_string_arg(config.get_or("first-names", "peter,paul,luke,john,saul,vicky,kate,julie,sarah,deiter,walter"));
         let last_names = split_string_arg(config.get_or("last-names", "shultz,abrams,spencer,white,bartels,walton,smith,jones,noris"));
+        let channels = config.get_as_or("channels", 10_000);
+        let hot_channel_ratio = config.get_as_or("hot-channel-ratio", 2);
+        let channel_urls = (0..HOT_CHANNELS.len()).map(channel_url)
+            .chain((0..channels).map(|c| format!("{}&channel_id={}", channel_url(HOT_CHANNELS.len() + c), c)))
+            .collect();
+        let bid_extra_size = config.get_as_or("bid-extra-size", 32);
         let rate_shape = if config.get_or("rate-shape", "sine") == "sine"{ RateShape::Sine }else{ RateShape::Square };
         let rate_period = config.get_as_or("rate-period", 600);
         let first_rate = config.get_as_or("first-event-rate", config.get_as_or("events-per-second", 10_000));
         let next_rate = config.get_as_or("next-event-rate", first_rate);
         let us_per_unit = config.get_as_or("us-per-unit", 1_000_000); // Rate is in μs
-        let generators = config.get_as_or("threads", 1) as f32;
+        let auction_distribution = match config.get("auction-distribution") {
+            Some(_) => Some(Distribution::from_config(config, "auction", in_flight_auctions, "uniform")?),
+            None => None
+        };
+        let bidder_distribution = match config.get("bidder-distribution") {
+            Some(_) => Some(Distribution::from_config(config, "bidder", active_people, "uniform")?),
+            None => None
+        };
         // Calculate inter event delays array.
         let mut inter_event_delays = Vec::new();
         let rate_to_period = |r| (us_per_unit) as f32 / r as f32;
         if first_rate == next_rate {
-            inter_event_delays.push(rate_to_period(first_rate) * generators);
+            inter_event_delays.push(rate_to_period(first_rate));
         } else {
             match rate_shape {
                 RateShape::Square => {
-                    inter_event_delays.push(rate_to_period(first_rate) * generators);
-                    inter_event_delays.push(rate_to_period(next_rate) * generators);
+                    inter_event_delays.push(rate_to_period(first_rate));
+                    inter_event_delays.push(rate_to_period(next_rate));
                 },
                 RateShape::Sine => {
                     let mid = (first_rate + next_rate) as f64 / 2.0;
@@ -144,7 +178,7 @@ impl NEXMarkConfig {
                     for i in 0..sine_approx_steps {
                         let r = (2.0 * PI * i as f64) / sine_approx_steps as f64;
                         let rate = mid + amp * r.cos();
-                        inter_event_delays.push(rate_to_period(rate.round() as usize) * generators);
+                        inter_event_delays.push(rate_to_period(rate.round() as usize));
                     }
                 }
             }
@@ -161,7 +195,7 @@ impl NEXMarkConfig {
                 epoch_period += (num_events_for_this_cycle * inter_event_delay) / 1000.0;
             }
         }
-        NEXMarkConfig {
+        Ok(NEXMarkConfig {
             active_people: active_people,
             in_flight_auctions: in_flight_auctions,
             out_of_order_group_size: out_of_order_group_size,
@@ -194,7 +228,13 @@ impl NEXMarkConfig {
             us_cities: us_cities,
             first_names: first_names,
             last_names: last_names,
-        }
+            channels: channels,
+            hot_channel_ratio: hot_channel_ratio,
+            channel_urls: channel_urls,
+            bid_extra_size: bid_extra_size,
+            auction_distribution: auction_distribution,
+            bidder_distribution: bidder_distribution,
+        })
     }
 
     fn event_timestamp(&self, event_number: usize) -> usize {
@@ -393,9 +433,16 @@ struct Bid{
     auction: Id,
     bidder: Id,
     price: usize,
+    // These were added later, default them so that older data still loads.
+    #[serde(default)]
+    channel: String,
+    #[serde(default)]
+    url: String,
     date_time: Date,
+    #[serde(default)]
+    extra: String,
 }
-unsafe_abomonate!(Bid : auction, bidder, price, date_time);
+unsafe_abomonate!(Bid : auction, bidder, price, channel, url, date_time, extra);
 
 impl Bid {
     fn from(event: Event) -> Option<Bid> {
@@ -406,21 +453,42 @@ impl Bid {
     }
     
     fn new(id: usize, time: Date, rng: &mut StdRng, nex: &NEXMarkConfig) -> Self {
-        let auction = if 0 < rng.gen_range(0, nex.hot_auction_ratio){
+        // The distributions pick how far back from the newest auction or
+        // person to go, so that the hot keys are the most recent ones.
+        let epoch = (time - nex.base_time) / 1000;
+        let auction = if let Some(ref dist) = nex.auction_distribution {
+            let last = Auction::last_id(id, nex);
+            last - min(dist.sample(rng, epoch), last)
+        } else if 0 < rng.gen_range(0, nex.hot_auction_ratio){
             (Auction::last_id(id, nex) / nex.hot_auction_ratio_2) * nex.hot_auction_ratio_2
         } else {
             Auction::next_id(id, rng, nex)
         };
-        let bidder = if 0 < rng.gen_range(0, nex.hot_bidder_ratio) {
+        let bidder = if let Some(ref dist) = nex.bidder_distribution {
+            let last = Person::last_id(id, nex);
+            last - min(dist.sample(rng, epoch), last)
+        } else if 0 < rng.gen_range(0, nex.hot_bidder_ratio) {
             (Person::last_id(id, nex) / nex.hot_bidder_ratio_2) * nex.hot_bidder_ratio_2 + 1
         } else {
             Person::next_id(id, rng, nex)
         };
+        let price = rng.gen_price();
+        // Hot channels share a fixed URL, the others carry their id in it.
+        let (channel, url) = if 0 < rng.gen_range(0, nex.hot_channel_ratio) {
+            let channel = rng.gen_range(0, HOT_CHANNELS.len());
+            (String::from(HOT_CHANNELS[channel]), nex.channel_urls[channel].clone())
+        } else {
+            let channel = rng.gen_range(0, nex.channels);
+            (format!("channel-{}", channel), nex.channel_urls[HOT_CHANNELS.len() + channel].clone())
+        };
         Bid {
             auction: auction + nex.first_auction_id,
             bidder: bidder + nex.first_person_id,
-            price: rng.gen_price(),
+            price: price,
+            channel: channel,
+            url: url,
             date_time: time,
+            extra: rng.gen_string(max(nex.bid_extra_size, MIN_STRING_LENGTH+1)),
         }
     }
 }
@@ -440,8 +508,8 @@ impl TestImpl for Query0 {
 
     fn name(&self) -> &str { "NEXMark Query 0" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
@@ -465,8 +533,8 @@ impl TestImpl for Query1 {
 
     fn name(&self) -> &str { "NEXMark Query 1" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
@@ -498,8 +566,8 @@ impl TestImpl for Query2 {
 
     fn name(&self) -> &str { "NEXMark Query 2" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
@@ -533,12 +601,12 @@ impl TestImpl for Query3 {
 
     fn name(&self) -> &str { "NEXMark Query 3" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
-    fn construct_dataflow<'scope>(&self, _c: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
         let auctions = stream
             .filter_map(|e| Auction::from(e))
             .filter(|a| a.category == 10);
@@ -547,8 +615,14 @@ impl TestImpl for Query3 {
             .filter_map(|e| Person::from(e))
             .filter(|p| p.state=="OR" || p.state=="ID" || p.state=="CA");
         
-        persons.left_join(&auctions, |p| p.id, |a| a.seller,
-                          |p, a| (p.name, p.city, p.state, a.id))
+        let (joined, sizes) = persons.bounded_left_join(&auctions, join_retention(config), |p| p.id, |a| a.seller,
+                                                        |p, a| (p.name, p.city, p.state, a.id));
+        if config.get_as_or("join-state-log", false) {
+            sizes.inspect_batch(|t, sizes| for size in sizes {
+                eprintln!("{:?}: {} records in the join state.", t, size);
+            });
+        }
+        joined
     }
 }
 
@@ -558,6 +632,17 @@ impl FromData<usize> for (String, String, String, Id) {
     }
 }
 
+/// Reads how many records the joins of the queries may keep from the config.
+///
+/// Without any of the options, joins keep all of their records forever.
+fn join_retention(config: &Config) -> JoinRetention {
+    JoinRetention {
+        ttl: config.get_as("join-ttl"),
+        max_per_key: config.get_as("join-max-per-key"),
+        capacity: config.get_as("join-capacity"),
+    }
+}
+
 struct Query4 {}
 
 impl Query4 {
@@ -573,13 +658,13 @@ impl TestImpl for Query4 {
 
     fn name(&self) -> &str { "NEXMark Query 4" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
     fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
-        hot_bids(stream, config.get_as_or("base-time", BASE_TIME))
+        hot_bids(stream, config).0
             .average_by(|&(ref a, _)| a.category, |(_, p)| p)
     }
 }
@@ -599,8 +684,8 @@ impl TestImpl for Query5 {
 
     fn name(&self) -> &str { "NEXMark Query 5" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
@@ -610,8 +695,7 @@ impl TestImpl for Query5 {
         
         let bids = stream
             .filter_map(|e| Bid::from(e))
-            .epoch_window(window_size, window_slide)
-            .reduce_by(|b| b.auction, 0, |_, c| c+1);
+            .window_reduce_by(window_size, window_slide, |b| b.auction, 0, |_, c| c+1, |a, b| a+b);
         
         let max = bids.reduce_to(0, |(_, p), c| max(p, c));
         
@@ -621,6 +705,39 @@ impl TestImpl for Query5 {
     }
 }
 
+/// Query 5 without gathering all bid counts on a single worker.
+struct Query5Scalable {}
+
+impl Query5Scalable {
+    fn new() -> Self {
+        Query5Scalable {}
+    }
+}
+
+impl TestImpl for Query5Scalable {
+    type T = Date;
+    type D = Event;
+    type DO = (Id, usize);
+
+    fn name(&self) -> &str { "NEXMark Query 5 Scalable" }
+    
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let window_size = config.get_as_or("window-size", 10) as usize;
+        let window_slide = config.get_as_or("window-slide", 5) as usize;
+        let fanout = config.get_as_or("fanout", 4);
+        
+        stream
+            .filter_map(|e| Bid::from(e))
+            .window_reduce_by(window_size, window_slide, |b| b.auction, 0, |_, c| c+1, |a, b| a+b)
+            .arg_max(fanout, |&(_, c)| c)
+    }
+}
+
 struct Query6 {}
 
 impl Query6 {
@@ -636,15 +753,16 @@ impl TestImpl for Query6 {
 
     fn name(&self) -> &str { "NEXMark Query 6" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
     fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
-        hot_bids(stream, config.get_as_or("base-time", BASE_TIME))
-            .partition(10, |&(ref a, _)| a.seller)
-            .map(|p| (p[0].1, p.iter().map(|p| p.1 as f32).sum::<f32>() / p.len() as f32))
+        // Every closed auction updates the average over its seller's last ten.
+        hot_bids(stream, config).0
+            .last_n(10, |&(ref a, _)| a.seller,
+                    |&seller, last| (seller, last.iter().map(|&(_, p)| p as f32).sum::<f32>() / last.len() as f32))
     }
 }
 
@@ -669,8 +787,8 @@ impl TestImpl for Query7 {
 
     fn name(&self) -> &str { "NEXMark Query 7" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
@@ -679,11 +797,11 @@ impl TestImpl for Query7 {
         
         stream
             .filter_map(|e| Bid::from(e))
-            .tumbling_window(window_size)
-            .reduce(|_| 0, (0, 0, 0), |b, (a, p, bi)| {
+            .window_reduce_by(window_size, window_size, |_| 0, (0, 0, 0), |b, (a, p, bi)| {
                 if p < b.price { (b.auction, b.price, b.bidder) }
                 else { (a, p, bi) }
-            }, |_, d, _| d)
+            }, |x, y| if x.1 < y.1 { y } else { x })
+            .map(|(_, d)| d)
     }
 }
 
@@ -708,24 +826,19 @@ impl TestImpl for Query8 {
 
     fn name(&self) -> &str { "NEXMark Query 8" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
     fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
         let window_size = config.get_as_or("window-size", 10) as usize;
         
-        let auctions = stream
-            .filter_map(|e| Auction::from(e))
-            .tumbling_window(window_size);
-        
-        let persons = stream
-            .filter_map(|e| Person::from(e))
-            .tumbling_window(window_size);
+        let auctions = stream.filter_map(|e| Auction::from(e));
+        let persons = stream.filter_map(|e| Person::from(e));
         
-        persons.epoch_join(&auctions, |p| p.id, |a| a.seller,
-                           |p, a| (p.id, p.name, a.reserve))
+        persons.window_join(&auctions, window_size, window_size, |p| p.id, |a| a.seller,
+                            |p, a| (p.id, p.name, a.reserve))
     }
 }
 
@@ -743,49 +856,103 @@ impl Query9 {
     }
 }
 
-fn hot_bids<'scope>(stream: &Stream<Child<'scope, Root<Generic>, Date>, Event>, base_time: usize) -> Stream<Child<'scope, Root<Generic>, Date>, (Auction, usize)> {
-    // FIXME: If bids arrive after the expiry of their auction
-    //        they will be retained forever. I'm actually not
-    //        sure how to remedy this beyond keeping /some/ data
-    //        indefinitely, like each auction's expiry time.
+/// What hot_bids does with bids that arrive after their auction closed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum LateBids {
+    Drop,
+    Divert,
+}
+
+impl LateBids {
+    fn from_config(config: &Config) -> Result<Self> {
+        match config.get_or("late-bids", "drop").as_ref() {
+            "drop" => Ok(LateBids::Drop),
+            "divert" => Ok(LateBids::Divert),
+            _ => Err(Error::new(ErrorKind::Other, "Unknown late bids policy."))
+        }
+    }
+}
+
+/// This describes for how long hot_bids remembers things that may no
+/// longer be needed, and thus bounds its state.
+#[derive(Clone, Copy, Debug)]
+struct Retention {
+    /// How many epochs to remember closed auctions, to recognise late bids.
+    closed: usize,
+    /// How many epochs to keep bids whose auction has not arrived yet.
+    orphans: usize,
+    late: LateBids,
+}
+
+impl Retention {
+    fn from_config(config: &Config) -> Result<Self> {
+        Ok(Retention {
+            closed: config.get_as_or("closed-auction-retention", 60),
+            orphans: config.get_as_or("orphan-bid-retention", 60),
+            late: LateBids::from_config(config)?,
+        })
+    }
+}
+
+/// An output of hot_bids.
+#[derive(Clone, Debug, Abomonation)]
+enum HotBid {
+    Winner(Auction, usize),
+    Late(Bid),
+}
+
+/// Returns the winning bid price of each auction when it expires, the bids
+/// that arrived too late if the retention diverts them, and the number of
+/// auctions and bids held on each worker.
+///
+/// Bids are joined with the auction they are for. Closed auctions are
+/// remembered for a while so that bids arriving after the expiry can be
+/// recognised as late, and bids for auctions that never show up are
+/// dropped after a while, so that the state stays bounded.
+fn hot_bids<G: Scope>(stream: &Stream<G, Event>, config: &Config) -> (Stream<G, (Auction, usize)>, Stream<G, Bid>, Stream<G, usize>)
+where G::Timestamp: IntegerTimestamp {
+    let base_time = config.get_as_or("base-time", BASE_TIME);
+    let retention = Retention::from_config(config).expect("NEXMark: Invalid retention policy.");
     let bids = stream.filter_map(|e| Bid::from(e));
     let auctions = stream.filter_map(|e| Auction::from(e));
-    
-    let mut auction_map = HashMap::new();
-    let mut bid_map: HashMap<Id, Vec<Bid>> = HashMap::new();
-    let auction_ex = Exchange::new(|a: &Auction| a.id as u64);
-    let bid_ex = Exchange::new(|b: &Bid| b.auction as u64);
-    
-    auctions.binary_notify(&bids, auction_ex, bid_ex, "HotBids", Vec::new(), move |input1, input2, output, notificator|{
-        input1.for_each(|time, data|{
-            data.drain(..).for_each(|a|{
-                let future = RootTimestamp::new(a.expires - base_time);
-                let auctions = auction_map.entry(future).or_insert_with(Vec::new);
-                auctions.push(a);
-                notificator.notify_at(time.delayed(&future));
-            });
-        });
 
-        input2.for_each(|_, data|{
-            data.drain(..).for_each(|b|{
-                bid_map.entry(b.auction).or_insert_with(Vec::new).push(b);
-            });
+    let (pairs, orphans, sizes) = auctions.interval_join(&bids, |a| a.id, |b| b.auction,
+                                                         move |a| ((a.date_time - base_time) / 1000, (a.expires - base_time) / 1000 + retention.closed),
+                                                         move |b| { let t = (b.date_time - base_time) / 1000; (t, t + retention.orphans) },
+                                                         |a, b| (a, b));
+
+    // The join leaves the pairs on the worker of their auction.
+    let mut auctions = HashMap::new();
+    let output = pairs.unary_notify(Pipeline, "HotBids", Vec::new(), move |input, output, notificator| {
+        input.for_each(|time, data| {
+            let epoch = time.time().to_integer();
+            let mut late = Vec::new();
+            for (a, b) in data.drain(..) {
+                let expiry = (a.expires - base_time) / 1000;
+                if a.expires <= b.date_time || expiry < epoch {
+                    late.push(HotBid::Late(b));
+                } else if a.reserve <= b.price {
+                    notificator.notify_at(time.delayed(&G::Timestamp::from_integer(expiry)));
+                    let winner = auctions.entry(expiry).or_insert_with(HashMap::new).entry(a.id).or_insert((a, 0));
+                    winner.1 = max(winner.1, b.price);
+                }
+            }
+            if !late.is_empty() {
+                output.session(&time).give_iterator(late.into_iter());
+            }
         });
-
-        notificator.for_each(|cap, _, _|{
-            if let Some(mut auctions) = auction_map.remove(cap.time()) {
-                auctions.drain(..).for_each(|a|{
-                    if let Some(mut bids) = bid_map.remove(&a.id) {
-                        bids.drain(..)
-                            .filter(|b| a.reserve <= b.price && b.date_time < a.expires)
-                            .map(|b| b.price)
-                            .max()
-                            .map(|price| output.session(&cap).give((a, price)));
-                    }
-                });
+        notificator.for_each(|cap, _, _| {
+            if let Some(winners) = auctions.remove(&cap.time().to_integer()) {
+                output.session(&cap).give_iterator(winners.into_iter().map(|(_, (a, p))| HotBid::Winner(a, p)));
             }
         });
-    })
+    });
+
+    (output.filter_map(|h| if let HotBid::Winner(a, p) = h { Some((a, p)) } else { None }),
+     output.filter_map(|h| if let HotBid::Late(b) = h { Some(b) } else { None })
+         .concat(&orphans)
+         .filter(move |_| retention.late == LateBids::Divert),
+     sizes)
 }
 
 impl TestImpl for Query9 {
@@ -795,13 +962,13 @@ impl TestImpl for Query9 {
 
     fn name(&self) -> &str { "NEXMark Query 9" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
     fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
-        hot_bids(stream, config.get_as_or("base-time", BASE_TIME))
+        hot_bids(stream, config).0
     }
 }
 
@@ -811,6 +978,152 @@ impl<T: Timestamp> FromData<T> for (Auction, usize) {
     }
 }
 
+/// Describes when a pane of a window was emitted.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug, Abomonation)]
+enum Timing {
+    /// The pane filled up before the window ended.
+    Early,
+    /// The pane was emitted when the window ended.
+    OnTime,
+    /// The pane contains bids that arrived after the window ended.
+    Late,
+}
+
+/// A batch of bids for one shard and window, as written out by Query 10.
+#[derive(Clone, Debug, Abomonation)]
+struct Pane {
+    shard: usize,
+    window: usize,
+    index: usize,
+    timing: Timing,
+    bids: Vec<Bid>,
+}
+
+/// This drain writes each pane into a file of its own.
+///
+/// The files are laid out as DIR/shard-S/window-W/pane-I-TIMING.json,
+/// and contain one bid per line.
+struct PaneDrain {
+    directory: String,
+}
+
+impl EventDrain<usize, Pane> for PaneDrain {
+    fn next(&mut self, _: usize, d: Vec<Pane>) {
+        for pane in d {
+            let directory = format!("{}/shard-{}/window-{}", self.directory, pane.shard, pane.window);
+            fs::create_dir_all(&directory).expect("NEXMark: Failed to create pane directory.");
+            let timing = match pane.timing {
+                Timing::Early => "early",
+                Timing::OnTime => "on-time",
+                Timing::Late => "late",
+            };
+            let mut file = File::create(format!("{}/pane-{}-{}.json", directory, pane.index, timing))
+                .expect("NEXMark: Failed to create pane file.");
+            for bid in pane.bids {
+                serde_json::to_writer(&file, &bid).expect("NEXMark: Failed to write bid.");
+                file.write(b"\n").expect("NEXMark: Failed to write bid.");
+            }
+        }
+    }
+}
+
+struct Query10 {}
+
+impl Query10 {
+    fn new() -> Self { Query10{} }
+}
+
+impl TestImpl for Query10 {
+    type T = Date;
+    type D = Event;
+    type DO = Pane;
+
+    fn name(&self) -> &str { "NEXMark Query 10" }
+    
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        let directory = config.get_or("log-dir", &format!("{}/nexmark/query10", config.get_or("data-dir", "data")));
+        fs::create_dir_all(&directory)?;
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::new(Box::new(PaneDrain{ directory: directory }))))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        log_shards(stream, config)
+    }
+}
+
+/// Splits the bids into panes per shard and window, as Query 10 logs them.
+///
+/// A pane is emitted early whenever pane-size bids have gathered, on time
+/// once the window ends, and late for every batch of bids that arrives
+/// after that, up to the allowed lateness.
+fn log_shards<G: Scope>(stream: &Stream<G, Event>, config: &Config) -> Stream<G, Pane>
+where G::Timestamp: IntegerTimestamp {
+    let window_size = config.get_as_or("window-size", 10) as usize;
+    let shards = config.get_as_or("shards", 10) as usize;
+    let pane_size = config.get_as_or("pane-size", 1000) as usize;
+    let base_time = config.get_as_or("base-time", BASE_TIME);
+    let lateness = config.get_as_or("allowed-lateness", 0) as usize;
+    let shard = move |b: &Bid| (b.auction * 31 + b.bidder) % shards;
+    let end = move |window: usize| (window + 1) * window_size - 1;
+    // For each shard and window, the pending bids, the index of the next
+    // pane, and whether the on-time pane has been emitted yet.
+    let mut panes: HashMap<(usize, usize), (Vec<Bid>, usize, bool)> = HashMap::new();
+
+    stream
+        .filter_map(|e| Bid::from(e))
+        .unary_notify(Exchange::new(move |b: &Bid| shard(b) as u64), "LogShards", Vec::new(), move |input, output, notificator| {
+            input.for_each(|cap, data| {
+                let epoch = cap.time().to_integer();
+                let mut session = output.session(&cap);
+                let mut touched = HashSet::new();
+                for bid in data.drain(..) {
+                    let window = (bid.date_time - base_time) / 1000 / window_size;
+                    // Bids past the allowed lateness are dropped.
+                    if end(window) + lateness < epoch { continue; }
+                    let key = (shard(&bid), window);
+                    let &mut (ref mut bids, ref mut index, fired) = panes.entry(key).or_insert_with(|| {
+                        // A window whose first bid comes after its end has
+                        // missed its on-time pane, so all its bids are late.
+                        let fired = end(window) < epoch;
+                        if !fired {
+                            notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(end(window))));
+                        }
+                        notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(end(window) + lateness)));
+                        (Vec::new(), 0, fired)
+                    });
+                    bids.push(bid);
+                    if fired {
+                        touched.insert(key);
+                    } else if pane_size <= bids.len() {
+                        session.give(Pane{ shard: key.0, window: window, index: *index, timing: Timing::Early, bids: bids.split_off(0) });
+                        *index += 1;
+                    }
+                }
+                // Bids for windows that were already emitted go out as late panes.
+                for key in touched {
+                    if let Some(&mut (ref mut bids, ref mut index, _)) = panes.get_mut(&key) {
+                        session.give(Pane{ shard: key.0, window: key.1, index: *index, timing: Timing::Late, bids: bids.split_off(0) });
+                        *index += 1;
+                    }
+                }
+            });
+
+            notificator.for_each(|cap, _, _| {
+                let epoch = cap.time().to_integer();
+                let mut session = output.session(&cap);
+                for (&(s, window), &mut (ref mut bids, ref mut index, ref mut fired)) in panes.iter_mut() {
+                    if !*fired && end(window) <= epoch {
+                        session.give(Pane{ shard: s, window: window, index: *index, timing: Timing::OnTime, bids: bids.split_off(0) });
+                        *index += 1;
+                        *fired = true;
+                    }
+                }
+                panes.retain(|&(_, window), _| epoch < end(window) + lateness);
+            });
+        })
+}
+
 struct Query11 {}
 
 impl Query11 {
@@ -824,15 +1137,16 @@ impl TestImpl for Query11 {
 
     fn name(&self) -> &str { "NEXMark Query 11" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
-    fn construct_dataflow<'scope>(&self, _c: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let base_time = config.get_as_or("base-time", BASE_TIME);
         stream
             .filter_map(|e| Bid::from(e))
-            .session(10, |b| (b.bidder, b.date_time / 1000))
+            .session(10, move |b| (b.bidder, (b.date_time - base_time) / 1000))
             .map(|(b, d)| (b, d.len()))
     }
 }
@@ -850,54 +1164,671 @@ impl TestImpl for Query12 {
 
     fn name(&self) -> &str { "NEXMark Query 12" }
     
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config))))?,
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        // The window size is given in seconds of processing time.
+        let size = config.get_as_or("window-size", 10) * 1000;
+        let clock = clock::from_config(config).expect("NEXMark: Failed to create the clock.");
+        stream
+            .filter_map(|e| Bid::from(e))
+            .processing_time_window(size, clock, |b| b.bidder)
+            .map(|((b, _), bids)| (b, bids.len()))
+    }
+}
+
+/// Maintains a state per key over all bids seen so far.
+///
+/// Every bid is folded into the state of its key by the update function,
+/// which returns whether the state changed. At the end of each epoch the
+/// finished state of every key that changed during it is emitted, which
+/// yields the update stream of an unbounded group-by.
+///
+/// The expiry function returns the epoch after which the state of a key,
+/// given the epoch of its latest bid, is dropped, or None to keep it
+/// forever. Bids for keys that have already expired are ignored.
+fn bid_updates<G: Scope, K, S, O, E, U, F>(bids: &Stream<G, Bid>, key: fn(&Bid)->K, expiry: E, update: U, finish: F) -> Stream<G, O>
+where G::Timestamp: IntegerTimestamp+Hash,
+      K: Hash+Eq+Clone+'static,
+      S: Default+'static,
+      O: Data,
+      E: Fn(&K, usize)->Option<usize>+'static,
+      U: Fn(&mut S, Bid)->bool+'static,
+      F: Fn(&K, &S)->O+'static {
+    // The state per key, along with the epoch on which it is dropped.
+    let mut states: HashMap<K, (Option<usize>, S)> = HashMap::new();
+    let mut changed: HashMap<G::Timestamp, HashSet<K>> = HashMap::new();
+    // The keys that may be dropped on each epoch.
+    let mut expiring: HashMap<usize, HashSet<K>> = HashMap::new();
+    let exchange = Exchange::new(move |b: &Bid| {
+        let mut h: ::fnv::FnvHasher = Default::default();
+        key(b).hash(&mut h);
+        h.finish()
+    });
+
+    bids.unary_notify(exchange, "BidUpdates", Vec::new(), move |input, output, notificator| {
+        input.for_each(|cap, data| {
+            let epoch = cap.time().to_integer();
+            let touched = changed.entry(cap.time().clone()).or_insert_with(HashSet::new);
+            for bid in data.drain(..) {
+                let k = key(&bid);
+                let until = expiry(&k, epoch);
+                if until.map_or(false, |u| u < epoch) { continue; }
+                if let Some(u) = until {
+                    notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(u)));
+                    expiring.entry(u).or_insert_with(HashSet::new).insert(k.clone());
+                }
+                let state = states.entry(k.clone()).or_insert_with(|| (None, S::default()));
+                state.0 = until;
+                if update(&mut state.1, bid) {
+                    touched.insert(k);
+                }
+            }
+            notificator.notify_at(cap.retain());
+        });
+
+        notificator.for_each(|cap, _, _| {
+            if let Some(touched) = changed.remove(cap.time()) {
+                output.session(&cap).give_iterator(touched.iter().map(|k| finish(k, &states[k].1)));
+            }
+            let epoch = cap.time().to_integer();
+            for k in expiring.remove(&epoch).unwrap_or_else(HashSet::new) {
+                // Later bids may have pushed the expiry of the key back.
+                if states.get(&k).map_or(false, |s| s.0 == Some(epoch)) {
+                    states.remove(&k);
+                }
+            }
+        });
+    })
+}
+
+fn day(date_time: Date) -> Date {
+    date_time / (24 * 60 * 60 * 1000)
+}
+
+/// Returns the epoch at which the given day ends.
+fn day_end(day: Date, base_time: Date) -> usize {
+    ((day + 1) * 24 * 60 * 60 * 1000).saturating_sub(base_time) / 1000
+}
+
+/// Reads for how many epochs after their latest bid queries 18 and 19 keep
+/// the state of a key.
+fn state_ttl(config: &Config) -> Option<usize> {
+    config.get_as("state-ttl")
+}
+
+/// Returns the price rank of a bid, as used by queries 15 to 17.
+fn price_rank(price: usize) -> usize {
+    if price < 10_000 { 1 }
+    else if price < 1_000_000 { 2 }
+    else { 3 }
+}
+
+struct Query13 {}
+
+impl Query13 {
+    fn new() -> Self { Query13{} }
+}
+
+impl TestImpl for Query13 {
+    type T = Date;
+    type D = Event;
+    type DO = (Bid, String);
+
+    fn name(&self) -> &str { "NEXMark Query 13" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let size = max(config.get_as_or("side-input-size", 10_000), 1);
+        side_input(&stream.filter_map(|e| Bid::from(e)), size)
+    }
+}
+
+/// Joins every bid with the value of its auction in a bounded side input.
+///
+/// The side input is small enough to be replicated on every worker.
+fn side_input<G: Scope>(bids: &Stream<G, Bid>, size: usize) -> Stream<G, (Bid, String)> {
+    let side: Vec<String> = (0..size).map(|i| format!("side-{}", i)).collect();
+    bids.map(move |b| { let value = side[b.auction % side.len()].clone(); (b, value) })
+}
+
+impl<T: Timestamp> FromData<T> for (Bid, String) {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+/// A bid with its price in euros and the time of day classified.
+#[derive(Clone, Debug, Abomonation)]
+struct ConvertedBid {
+    auction: Id,
+    bidder: Id,
+    price: f32,
+    time_type: String,
+    date_time: Date,
+    extra: String,
+    c_count: usize,
+}
+
+impl<T: Timestamp> FromData<T> for ConvertedBid {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query14 {}
+
+impl Query14 {
+    fn new() -> Self { Query14{} }
+}
+
+impl TestImpl for Query14 {
+    type T = Date;
+    type D = Event;
+    type DO = ConvertedBid;
+
+    fn name(&self) -> &str { "NEXMark Query 14" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
             Drain::from_config(config)?))
     }
 
     fn construct_dataflow<'scope>(&self, _c: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
-        let start = Instant::now();
         stream
             .filter_map(|e| Bid::from(e))
-            .session(10, move |b| {
-                let d = Instant::now().duration_since(start);
-                (b.bidder, d.as_secs() as usize)
+            .map(|b| (b.price as f32 * 0.908, b))
+            .filter(|&(p, _)| 1_000_000.0 < p && p < 50_000_000.0)
+            .map(|(price, b)| {
+                let hour = (b.date_time / (60 * 60 * 1000)) % 24;
+                let time_type = if 8 <= hour && hour <= 18 { "dayTime" }
+                                else if hour <= 6 || 20 <= hour { "nightTime" }
+                                else { "otherTime" };
+                ConvertedBid {
+                    auction: b.auction,
+                    bidder: b.bidder,
+                    price: price,
+                    time_type: String::from(time_type),
+                    date_time: b.date_time,
+                    c_count: b.extra.matches('c').count(),
+                    extra: b.extra,
+                }
+            })
+    }
+}
+
+/// Counts bids as well as distinct bidders and auctions, in total and per price rank.
+#[derive(Default)]
+struct DistinctCounter {
+    bids: [usize; 4],
+    bidders: [HashSet<Id>; 4],
+    auctions: [HashSet<Id>; 4],
+}
+
+impl DistinctCounter {
+    fn add(&mut self, bid: Bid) -> bool {
+        for &i in &[0, price_rank(bid.price)] {
+            self.bids[i] += 1;
+            self.bidders[i].insert(bid.bidder);
+            self.auctions[i].insert(bid.auction);
+        }
+        true
+    }
+
+    fn statistics(&self, day: Date) -> DailyStatistics {
+        DailyStatistics {
+            day: day,
+            total_bids: self.bids[0],
+            rank1_bids: self.bids[1],
+            rank2_bids: self.bids[2],
+            rank3_bids: self.bids[3],
+            total_bidders: self.bidders[0].len(),
+            rank1_bidders: self.bidders[1].len(),
+            rank2_bidders: self.bidders[2].len(),
+            rank3_bidders: self.bidders[3].len(),
+            total_auctions: self.auctions[0].len(),
+            rank1_auctions: self.auctions[1].len(),
+            rank2_auctions: self.auctions[2].len(),
+            rank3_auctions: self.auctions[3].len(),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, Abomonation)]
+struct DailyStatistics {
+    day: Date,
+    total_bids: usize,
+    rank1_bids: usize,
+    rank2_bids: usize,
+    rank3_bids: usize,
+    total_bidders: usize,
+    rank1_bidders: usize,
+    rank2_bidders: usize,
+    rank3_bidders: usize,
+    total_auctions: usize,
+    rank1_auctions: usize,
+    rank2_auctions: usize,
+    rank3_auctions: usize,
+}
+
+impl<T: Timestamp> FromData<T> for DailyStatistics {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+impl<T: Timestamp> FromData<T> for (String, DailyStatistics) {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query15 {}
+
+impl Query15 {
+    fn new() -> Self { Query15{} }
+}
+
+impl TestImpl for Query15 {
+    type T = Date;
+    type D = Event;
+    type DO = DailyStatistics;
+
+    fn name(&self) -> &str { "NEXMark Query 15" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let base_time = config.get_as_or("base-time", BASE_TIME);
+        let bids = stream.filter_map(|e| Bid::from(e));
+        bid_updates(&bids, |b| day(b.date_time),
+                    move |&day, _| Some(day_end(day, base_time)),
+                    |s: &mut DistinctCounter, b| s.add(b),
+                    |&day, s| s.statistics(day))
+    }
+}
+
+struct Query16 {}
+
+impl Query16 {
+    fn new() -> Self { Query16{} }
+}
+
+impl TestImpl for Query16 {
+    type T = Date;
+    type D = Event;
+    type DO = (String, DailyStatistics);
+
+    fn name(&self) -> &str { "NEXMark Query 16" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let base_time = config.get_as_or("base-time", BASE_TIME);
+        let bids = stream.filter_map(|e| Bid::from(e));
+        bid_updates(&bids, |b| (b.channel.clone(), day(b.date_time)),
+                    move |&(_, day), _| Some(day_end(day, base_time)),
+                    |s: &mut DistinctCounter, b| s.add(b),
+                    |&(ref channel, day), s| (channel.clone(), s.statistics(day)))
+    }
+}
+
+/// Counts bids per price rank and tracks their prices.
+#[derive(Default)]
+struct PriceCounter {
+    bids: [usize; 4],
+    min: usize,
+    max: usize,
+    sum: usize,
+}
+
+#[derive(Eq, PartialEq, Clone, Debug, Abomonation)]
+struct AuctionStatistics {
+    auction: Id,
+    day: Date,
+    total_bids: usize,
+    rank1_bids: usize,
+    rank2_bids: usize,
+    rank3_bids: usize,
+    min_price: usize,
+    max_price: usize,
+    avg_price: usize,
+    sum_price: usize,
+}
+
+impl<T: Timestamp> FromData<T> for AuctionStatistics {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query17 {}
+
+impl Query17 {
+    fn new() -> Self { Query17{} }
+}
+
+impl TestImpl for Query17 {
+    type T = Date;
+    type D = Event;
+    type DO = AuctionStatistics;
+
+    fn name(&self) -> &str { "NEXMark Query 17" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let base_time = config.get_as_or("base-time", BASE_TIME);
+        let bids = stream.filter_map(|e| Bid::from(e));
+        bid_updates(&bids, |b| (b.auction, day(b.date_time)),
+                    move |&(_, day), _| Some(day_end(day, base_time)),
+                    |s: &mut PriceCounter, b| {
+                        s.min = if s.bids[0] == 0 { b.price } else { min(s.min, b.price) };
+                        s.max = max(s.max, b.price);
+                        s.sum += b.price;
+                        s.bids[0] += 1;
+                        s.bids[price_rank(b.price)] += 1;
+                        true
+                    },
+                    |&(auction, day), s| AuctionStatistics {
+                        auction: auction,
+                        day: day,
+                        total_bids: s.bids[0],
+                        rank1_bids: s.bids[1],
+                        rank2_bids: s.bids[2],
+                        rank3_bids: s.bids[3],
+                        min_price: s.min,
+                        max_price: s.max,
+                        avg_price: s.sum / s.bids[0],
+                        sum_price: s.sum,
+                    })
+    }
+}
+
+impl<T: Timestamp> FromData<T> for Bid {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query18 {}
+
+impl Query18 {
+    fn new() -> Self { Query18{} }
+}
+
+impl TestImpl for Query18 {
+    type T = Date;
+    type D = Event;
+    type DO = Bid;
+
+    fn name(&self) -> &str { "NEXMark Query 18" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        // Deduplicate to the latest bid of each bidder on each auction.
+        let ttl = state_ttl(config);
+        let bids = stream.filter_map(|e| Bid::from(e));
+        bid_updates(&bids, |b| (b.bidder, b.auction),
+                    move |_, epoch| ttl.map(|t| epoch + t),
+                    |s: &mut Option<Bid>, b| {
+                        let newer = s.as_ref().map_or(true, |l| l.date_time <= b.date_time);
+                        if newer { *s = Some(b); }
+                        newer
+                    },
+                    |_, s| s.clone().unwrap())
+    }
+}
+
+impl<T: Timestamp> FromData<T> for (Id, Vec<Bid>) {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query19 {}
+
+impl Query19 {
+    fn new() -> Self { Query19{} }
+}
+
+impl TestImpl for Query19 {
+    type T = Date;
+    type D = Event;
+    type DO = (Id, Vec<Bid>);
+
+    fn name(&self) -> &str { "NEXMark Query 19" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let n = config.get_as_or("top-bids", 10);
+        let bids = stream.filter_map(|e| Bid::from(e));
+        let ttl = state_ttl(config);
+        // Keep the top bids of each auction sorted by descending price.
+        bid_updates(&bids, |b| b.auction,
+                    move |_, epoch| ttl.map(|t| epoch + t),
+                    move |s: &mut Vec<Bid>, b| {
+                        let i = s.iter().position(|t| t.price < b.price).unwrap_or(s.len());
+                        if i < n { s.insert(i, b); s.truncate(n); }
+                        i < n
+                    },
+                    |&auction, s| (auction, s.clone()))
+    }
+}
+
+impl<T: Timestamp> FromData<T> for (Bid, Auction) {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query20 {}
+
+impl Query20 {
+    fn new() -> Self { Query20{} }
+}
+
+impl TestImpl for Query20 {
+    type T = Date;
+    type D = Event;
+    type DO = (Bid, Auction);
+
+    fn name(&self) -> &str { "NEXMark Query 20" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, _c: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let auctions = stream.filter_map(|e| Auction::from(e));
+        let bids = stream.filter_map(|e| Bid::from(e));
+        // Filter after the join, as bids of other auctions would wait
+        // for their auction indefinitely otherwise.
+        auctions.left_join(&bids, |a| a.id, |b| b.auction, |a, b| (b, a))
+            .filter(|&(_, ref a)| a.category == 10)
+    }
+}
+
+/// A bid along with the id of its channel.
+#[derive(Clone, Debug, Abomonation)]
+struct ChannelBid {
+    auction: Id,
+    bidder: Id,
+    price: usize,
+    channel: String,
+    channel_id: String,
+}
+
+impl<T: Timestamp> FromData<T> for ChannelBid {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query21 {}
+
+impl Query21 {
+    fn new() -> Self { Query21{} }
+}
+
+impl TestImpl for Query21 {
+    type T = Date;
+    type D = Event;
+    type DO = ChannelBid;
+
+    fn name(&self) -> &str { "NEXMark Query 21" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, _c: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        stream
+            .filter_map(|e| Bid::from(e))
+            .map(|b| {
+                let channel_id = match b.channel.to_lowercase().as_ref() {
+                    "apple" => String::from("0"),
+                    "google" => String::from("1"),
+                    "facebook" => String::from("2"),
+                    "baidu" => String::from("3"),
+                    _ => b.url.split(|c| c == '?' || c == '&')
+                        .find(|p| p.starts_with("channel_id="))
+                        .map(|p| String::from(&p["channel_id=".len()..]))
+                        .unwrap_or_default()
+                };
+                ChannelBid {
+                    auction: b.auction,
+                    bidder: b.bidder,
+                    price: b.price,
+                    channel: b.channel,
+                    channel_id: channel_id,
+                }
+            })
+            .filter(|b| !b.channel_id.is_empty())
+    }
+}
+
+/// A bid along with the first directories of its URL's path.
+#[derive(Clone, Debug, Abomonation)]
+struct DirectoryBid {
+    auction: Id,
+    bidder: Id,
+    price: usize,
+    channel: String,
+    dir1: String,
+    dir2: String,
+    dir3: String,
+}
+
+impl<T: Timestamp> FromData<T> for DirectoryBid {
+    fn from_data(&self, t: &T) -> String {
+        format!("{:?} {:?}", t, self)
+    }
+}
+
+struct Query22 {}
+
+impl Query22 {
+    fn new() -> Self { Query22{} }
+}
+
+impl TestImpl for Query22 {
+    type T = Date;
+    type D = Event;
+    type DO = DirectoryBid;
+
+    fn name(&self) -> &str { "NEXMark Query 22" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        Ok((Source::from_config(config, Source::new(Box::new(NEXMarkGenerator::new(config, index, workers)?)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, _c: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        stream
+            .filter_map(|e| Bid::from(e))
+            .map(|b| {
+                // The path starts after the scheme and the host.
+                let dirs = b.url.split('/').skip(3).map(String::from).collect::<Vec<_>>();
+                let dir = |i: usize| dirs.get(i).cloned().unwrap_or_default();
+                DirectoryBid {
+                    auction: b.auction,
+                    bidder: b.bidder,
+                    price: b.price,
+                    dir1: dir(0),
+                    dir2: dir(1),
+                    dir3: dir(2),
+                    channel: b.channel,
+                }
             })
-            .map(|(b, d)| (b, d.len()))
     }
 }
 
 // FIXME: Merge this with NEXMarkConfig
+/// Generates this worker's share of the NEXMark event sequence.
+///
+/// The sequence is interleaved across all workers by event number, so that
+/// worker i produces events i, i+n, i+2n, and so on. Together all workers
+/// thus produce exactly the stream a single generator would, at the
+/// configured rate.
 #[derive(Clone)]
 pub struct NEXMarkGenerator {
     config: NEXMarkConfig,
     events: usize,
+    step: usize,
     seconds: usize
 }
 
 impl NEXMarkGenerator {
-    fn new(config: &Config) -> Self {
-        NEXMarkGenerator {
-            config: NEXMarkConfig::new(config),
-            events: 0,
+    fn new(config: &Config, index: usize, workers: usize) -> Result<Self> {
+        Ok(NEXMarkGenerator {
+            config: NEXMarkConfig::new(config)?,
+            events: index,
+            step: workers,
             seconds: config.get_as_or("seconds", 60)
-        }
+        })
     }
 }
 
 impl EventSource<usize, Event> for NEXMarkGenerator {
     fn next(&mut self) -> Result<(usize, Vec<Event>)> {
-        let mut data = Vec::with_capacity((1000.0 / self.config.inter_event_delays[0]) as usize);
+        let per_second = 1_000_000.0 / (self.config.inter_event_delays[0] * self.step as f32);
+        let mut data = Vec::with_capacity(per_second as usize);
         let epoch = (self.config.event_timestamp(self.events + self.config.first_event_id) - self.config.base_time) / 1000;
         
         loop {
             let time = self.config.event_timestamp(self.events + self.config.first_event_id);
             let next_epoch = (time - self.config.base_time) / 1000;
-            let event = Event::new(self.events, &mut self.config);
 
             if next_epoch < self.seconds && next_epoch == epoch {
-                self.events += 1;
-                data.push(event);
+                data.push(Event::new(self.events, &mut self.config));
+                self.events += self.step;
             } else {
                 break;
             }
@@ -929,12 +1860,16 @@ impl Benchmark for NEXMark {
 
         println!("Generating events for {}s over {} partitions.", seconds, partitions);
 
-        let generator =  NEXMarkGenerator::new(config);
+        let generators = (0..partitions)
+            .map(|p| NEXMarkGenerator::new(config, p, partitions))
+            .collect::<Result<Vec<_>>>()?;
+        if config.get_as_or("benchmark", false) {
+            return endpoint::benchmark_generators(generators);
+        }
 
         let mut threads: Vec<JoinHandle<Result<()>>> = Vec::new();
-        for p in 0..partitions {
+        for (p, mut generator) in generators.into_iter().enumerate() {
             let mut file = File::create(format!("{}/events-{}.json", &data_dir, p))?;
-            let mut generator = generator.clone();
             threads.push(thread::spawn(move || {
                 loop{
                     let (t, d) = generator.next()?;
@@ -958,13 +1893,238 @@ impl Benchmark for NEXMark {
              Box::new(Query3::new()),
              Box::new(Query4::new()),
              Box::new(Query5::new()),
+             Box::new(Query5Scalable::new()),
              Box::new(Query6::new()),
              Box::new(Query7::new()),
              Box::new(Query8::new()),
              Box::new(Query9::new()),
+             Box::new(Query10::new()),
              Box::new(Query11::new()),
-             //Box::new(Query12::new())
+             Box::new(Query12::new()),
+             Box::new(Query13::new()),
+             Box::new(Query14::new()),
+             Box::new(Query15::new()),
+             Box::new(Query16::new()),
+             Box::new(Query17::new()),
+             Box::new(Query18::new()),
+             Box::new(Query19::new()),
+             Box::new(Query20::new()),
+             Box::new(Query21::new()),
+             Box::new(Query22::new())
         ]
     }
 
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timely;
+    use timely::dataflow::operators::{ToStream, Capture, Delay, Map};
+    use timely::dataflow::operators::capture::Extract;
+    use timely::progress::timestamp::RootTimestamp;
+
+    fn auction(id: Id, reserve: usize, expires: Date) -> Auction {
+        Auction {
+            id: id,
+            item_name: String::new(),
+            description: String::new(),
+            initial_bid: 0,
+            reserve: reserve,
+            date_time: 0,
+            expires: expires,
+            seller: 0,
+            category: 0,
+        }
+    }
+
+    fn bid(auction: Id, price: usize, date_time: Date) -> Bid {
+        Bid {
+            auction: auction,
+            bidder: 0,
+            price: price,
+            channel: String::new(),
+            url: String::new(),
+            date_time: date_time,
+            extra: String::new(),
+        }
+    }
+
+    #[test]
+    fn hot_bids() {
+        let mut config = Config::new();
+        config.insert("base-time", String::from("0"));
+        config.insert("late-bids", String::from("divert"));
+        config.insert("closed-auction-retention", String::from("10"));
+        config.insert("orphan-bid-retention", String::from("5"));
+        let (winners, late) = timely::example(move |scope| {
+            let events = vec!((0, Event::Auction(auction(1, 10, 2500))),
+                              (1, Event::Bid(bid(1, 5, 1000))),
+                              (1, Event::Bid(bid(1, 20, 1500))),
+                              (1, Event::Bid(bid(2, 20, 1500))),
+                              (2, Event::Bid(bid(1, 30, 2600))),
+                              (3, Event::Bid(bid(1, 40, 3000))))
+                .to_stream(scope)
+                .delay(|e, _| RootTimestamp::new(e.0))
+                .map(|e| e.1);
+            let (winners, late, _) = super::hot_bids(&events, &config);
+            (winners.map(|(a, p)| (a.id, p)).capture(), late.map(|b| (b.auction, b.price)).capture())
+        });
+
+        // The bid at 2600 comes after the expiry, and the auction of the
+        // bid at 3000 already closed. The auction of the bid at 1500 never
+        // shows up, so it is diverted once the orphan retention ran out.
+        assert_eq!(winners.extract(), vec!((RootTimestamp::new(2), vec!((1, 20)))));
+        assert_eq!(late.extract(), vec!((RootTimestamp::new(2), vec!((1, 30))),
+                                        (RootTimestamp::new(3), vec!((1, 40))),
+                                        (RootTimestamp::new(6), vec!((2, 20)))));
+    }
+
+    #[test]
+    fn log_shards() {
+        let mut config = Config::new();
+        config.insert("base-time", String::from("0"));
+        config.insert("window-size", String::from("2"));
+        config.insert("shards", String::from("1"));
+        config.insert("pane-size", String::from("3"));
+        config.insert("allowed-lateness", String::from("2"));
+        let panes = timely::example(move |scope| {
+            let events = vec!((0, Event::Bid(bid(1, 1, 0))),
+                              (0, Event::Bid(bid(1, 2, 500))),
+                              (0, Event::Bid(bid(1, 3, 900))),
+                              (1, Event::Bid(bid(1, 4, 1500))),
+                              (2, Event::Bid(bid(1, 5, 800))),
+                              (4, Event::Bid(bid(1, 6, 2500))),
+                              (5, Event::Bid(bid(1, 7, 100))))
+                .to_stream(scope)
+                .delay(|e, _| RootTimestamp::new(e.0))
+                .map(|e| e.1);
+            super::log_shards(&events, &config)
+                .map(|p| (p.window, p.index, p.timing, p.bids.iter().map(|b| b.price).collect::<Vec<_>>()))
+                .capture()
+        });
+
+        // The first window fills a pane early, ends at epoch 1, and gets a
+        // late bid after. The first bid of the second window only arrives
+        // after its end, and the bid at epoch 5 is past the lateness.
+        assert_eq!(panes.extract(), vec!((RootTimestamp::new(0), vec!((0, 0, Timing::Early, vec!(1, 2, 3)))),
+                                         (RootTimestamp::new(1), vec!((0, 1, Timing::OnTime, vec!(4)))),
+                                         (RootTimestamp::new(2), vec!((0, 2, Timing::Late, vec!(5)))),
+                                         (RootTimestamp::new(4), vec!((1, 0, Timing::Late, vec!(6))))));
+    }
+
+    #[test]
+    fn partitioned_generators() {
+        let mut config = Config::new();
+        config.insert("events-per-second", String::from("300"));
+        config.insert("seconds", String::from("20"));
+        let drain = |mut generator: NEXMarkGenerator| {
+            let mut epochs = Vec::new();
+            let mut events = Vec::new();
+            while let Ok((epoch, data)) = generator.next() {
+                epochs.push(epoch);
+                events.extend(data.into_iter().map(|e| (epoch, serde_json::to_string(&e).unwrap())));
+            }
+            (epochs, events)
+        };
+
+        let (epochs, mut expected) = drain(NEXMarkGenerator::new(&config, 0, 1).unwrap());
+        let mut events = Vec::new();
+        for i in 0..3 {
+            let (worker_epochs, worker_events) = drain(NEXMarkGenerator::new(&config, i, 3).unwrap());
+            assert_eq!(worker_epochs, epochs);
+            events.extend(worker_events);
+        }
+        // The workers together produce exactly the events of a single generator.
+        expected.sort();
+        events.sort();
+        assert_eq!(events, expected);
+    }
+
+    #[test]
+    fn hot_bids_bounded() {
+        let mut config = Config::new();
+        config.insert("events-per-second", String::from("200"));
+        config.insert("seconds", String::from("400"));
+        let mut generator = NEXMarkGenerator::new(&config, 0, 1).unwrap();
+        let mut events = Vec::new();
+        while let Ok((epoch, data)) = generator.next() {
+            events.extend(data.into_iter().map(|e| (epoch, e)));
+        }
+        let sizes = timely::example(move |scope| {
+            let events = events
+                .to_stream(scope)
+                .delay(|e, _| RootTimestamp::new(e.0))
+                .map(|e| e.1);
+            super::hot_bids(&events, &config).2.capture()
+        });
+
+        let sizes: Vec<usize> = sizes.extract().into_iter()
+            .filter(|&(t, _)| t.inner < 400)
+            .map(|(_, s)| s[0])
+            .collect();
+        assert_eq!(sizes.len(), 400);
+        // Once the retention periods have passed the state no longer grows.
+        let early = *sizes[150..275].iter().max().unwrap();
+        let late = *sizes[275..400].iter().max().unwrap();
+        assert!(late <= early + early / 2, "State grew from {} to {}", early, late);
+    }
+
+    #[test]
+    fn side_input() {
+        let data = timely::example(|scope| {
+            let bids = vec!(bid(1, 0, 0), bid(5, 0, 0), bid(12, 0, 0))
+                .to_stream(scope);
+            super::side_input(&bids, 10)
+                .map(|(b, side)| (b.auction, side))
+                .capture()
+        });
+
+        assert_eq!(data.extract()[0].1, vec!((1, String::from("side-1")),
+                                             (5, String::from("side-5")),
+                                             (12, String::from("side-2"))));
+    }
+
+    #[test]
+    fn bid_updates() {
+        // Tracks the highest price per auction.
+        let data = timely::example(|scope| {
+            let bids = vec!((0, bid(1, 10, 0)), (0, bid(1, 5, 0)), (0, bid(2, 3, 0)),
+                            (1, bid(1, 4, 0)), (1, bid(2, 7, 0)))
+                .to_stream(scope)
+                .delay(|b, _| RootTimestamp::new(b.0))
+                .map(|b| b.1);
+            super::bid_updates(&bids, |b| b.auction, |_, _| None,
+                               |s: &mut usize, b| { let higher = *s < b.price; *s = max(*s, b.price); higher },
+                               |&auction, &s| (auction, s))
+                .capture()
+        });
+
+        // Only the auctions whose state changed are emitted at each epoch.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(0), vec!((1, 10), (2, 3))),
+                                        (RootTimestamp::new(1), vec!((2, 7)))));
+    }
+
+    #[test]
+    fn bid_updates_expiry() {
+        // The first auction expires at epoch 1, the others one epoch after their latest bid.
+        let data = timely::example(|scope| {
+            let bids = vec!((0, bid(1, 10, 0)), (0, bid(2, 10, 0)), (1, bid(2, 5, 0)),
+                            (2, bid(1, 30, 0)), (3, bid(2, 4, 0)))
+                .to_stream(scope)
+                .delay(|b, _| RootTimestamp::new(b.0))
+                .map(|b| b.1);
+            super::bid_updates(&bids, |b| b.auction,
+                               |&auction, epoch| Some(if auction == 1 { 1 } else { epoch + 1 }),
+                               |s: &mut usize, b| { let higher = *s < b.price; *s = max(*s, b.price); higher },
+                               |&auction, &s| (auction, s))
+                .capture()
+        });
+
+        // The bid at epoch 1 keeps the second auction until epoch 2. The bid
+        // for the first auction at epoch 2 comes after its expiry and is
+        // ignored, while the second auction starts over at epoch 3.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(0), vec!((1, 10), (2, 10))),
+                                        (RootTimestamp::new(3), vec!((2, 4)))));
+    }
+}
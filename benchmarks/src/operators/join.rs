@@ -1,10 +1,142 @@
-use std::collections::HashMap;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet, BTreeMap, VecDeque};
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
 use timely::Data;
-use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::channels::pact::{Pipeline, Exchange, ParallelizationContract};
+use timely::dataflow::operators::{Map, Unary};
 use timely::dataflow::operators::generic::Binary;
 use timely::dataflow::{Stream, Scope};
+use super::{IntegerTimestamp, FilterMap, gcd};
+
+/// The output of the bounded left join, before it is split into its streams.
+#[derive(Clone, Abomonation)]
+enum JoinOutput<D> {
+    Joined(D),
+    StateSize(usize),
+}
+
+/// The output of the interval join, before it is split into its streams.
+#[derive(Clone, Abomonation)]
+enum IntervalJoinOutput<D, U> {
+    Joined(D),
+    Unmatched(U),
+    StateSize(usize),
+}
+
+/// This describes how many records a join retains, and thus bounds its state.
+///
+/// The time to live applies to both sides of the join, whereas the limits on
+/// records and keys only apply to the left side. Right records that wait for
+/// their left record are thus only dropped once their time runs out. Every
+/// limit applies to every worker separately.
+#[derive(Clone, Copy, Debug)]
+pub struct JoinRetention {
+    /// For how many epochs after their arrival records are kept.
+    pub ttl: Option<usize>,
+    /// How many records are kept per key, dropping the oldest first.
+    pub max_per_key: Option<usize>,
+    /// How many keys are kept, dropping the least recently used first.
+    pub capacity: Option<usize>,
+}
+
+impl JoinRetention {
+    /// Keeps all records forever.
+    pub fn unbounded() -> Self {
+        JoinRetention{ ttl: None, max_per_key: None, capacity: None }
+    }
+}
+
+/// The records of one side of a join, along with their arrival epochs.
+struct JoinState<H, D> {
+    retention: JoinRetention,
+    /// The records by key, along with the last use of the key.
+    records: HashMap<H, (usize, VecDeque<(usize, D)>)>,
+    /// The keys in order of their last use.
+    recency: BTreeMap<usize, H>,
+    /// The keys that have records to expire on each epoch.
+    expiring: HashMap<usize, HashSet<H>>,
+    uses: usize,
+    size: usize,
+}
+
+impl<H: Hash+Eq+Clone, D> JoinState<H, D> {
+    fn new(retention: JoinRetention) -> Self {
+        JoinState{ retention: retention, records: HashMap::new(), recency: BTreeMap::new(), expiring: HashMap::new(), uses: 0, size: 0 }
+    }
+
+    fn touch(&mut self, key: &H) {
+        if let Some(&mut (ref mut used, _)) = self.records.get_mut(key) {
+            self.recency.remove(used);
+            self.uses += 1;
+            *used = self.uses;
+            self.recency.insert(self.uses, key.clone());
+        }
+    }
+
+    fn insert(&mut self, key: H, epoch: usize, data: D) {
+        {
+            let &mut (_, ref mut records) = self.records.entry(key.clone()).or_insert_with(|| (0, VecDeque::new()));
+            records.push_back((epoch, data));
+            self.size += 1;
+            if let Some(max) = self.retention.max_per_key {
+                while max < records.len() {
+                    records.pop_front();
+                    self.size -= 1;
+                }
+            }
+        }
+        if let Some(ttl) = self.retention.ttl {
+            self.expiring.entry(epoch + ttl).or_insert_with(HashSet::new).insert(key.clone());
+        }
+        self.touch(&key);
+        if let Some(capacity) = self.retention.capacity {
+            while capacity < self.records.len() {
+                let oldest = *self.recency.keys().next().unwrap();
+                let key = self.recency.remove(&oldest).unwrap();
+                self.remove(&key);
+            }
+        }
+    }
+
+    fn get(&mut self, key: &H) -> Option<&VecDeque<(usize, D)>> {
+        self.touch(key);
+        self.records.get(key).map(|&(_, ref records)| records)
+    }
+
+    fn remove(&mut self, key: &H) -> Option<VecDeque<(usize, D)>> {
+        self.records.remove(key).map(|(used, records)| {
+            self.recency.remove(&used);
+            self.size -= records.len();
+            records
+        })
+    }
+
+    /// Drops the records whose time to live ran out at the given epoch.
+    ///
+    /// This needs to be called on every epoch that records expire on.
+    fn expire(&mut self, epoch: usize) {
+        if let Some(ttl) = self.retention.ttl {
+            for key in self.expiring.remove(&epoch).unwrap_or_else(HashSet::new) {
+                let empty = match self.records.get_mut(&key) {
+                    Some(&mut (_, ref mut records)) => {
+                        while records.front().map_or(false, |&(e, _)| e + ttl <= epoch) {
+                            records.pop_front();
+                            self.size -= 1;
+                        }
+                        records.is_empty()
+                    },
+                    None => false
+                };
+                if empty { self.remove(&key); }
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.size
+    }
+}
 
 pub trait Join<G: Scope, D1: Data+Send> {
     fn epoch_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, joiner: J) -> Stream<G, D3>
@@ -19,7 +151,141 @@ pub trait Join<G: Scope, D1: Data+Send> {
           D2: Data+Send, D3: Data,
           K1: Fn(&D1)->H+'static,
           K2: Fn(&D2)->H+'static,
-          J: Fn(D1, D2)->D3+'static;
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn bounded_left_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, retention: JoinRetention, key_1: K1, key_2: K2, joiner: J) -> (Stream<G, D3>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn broadcast_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, joiner: J) -> Stream<G, D3>
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn bounded_broadcast_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, retention: JoinRetention, key_1: K1, key_2: K2, joiner: J) -> (Stream<G, D3>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn interval_join<H, D2, D3, K1, K2, I1, I2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, interval_1: I1, interval_2: I2, joiner: J) -> (Stream<G, D3>, Stream<G, D2>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          I1: Fn(&D1)->(usize, usize)+'static,
+          I2: Fn(&D2)->(usize, usize)+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn time_interval_join<H, D2, D3, K1, K2, T1, T2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, time_1: T1, time_2: T2, before: usize, after: usize, joiner: J) -> (Stream<G, D3>, Stream<G, D2>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          T1: Fn(&D1)->usize+'static,
+          T2: Fn(&D2)->usize+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn window_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, size: usize, slide: usize, key_1: K1, key_2: K2, joiner: J) -> Stream<G, D3>
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp;
+}
+
+/// Returns the last epochs of the sliding windows that contain the epoch.
+///
+/// Windows start at multiples of the slide.
+fn window_ends(epoch: usize, size: usize, slide: usize) -> Vec<usize> {
+    let first = if size <= epoch { (epoch - size) / slide + 1 } else { 0 };
+    (first..epoch/slide+1).map(|k| k * slide + size - 1).filter(|&end| epoch <= end).collect()
+}
+
+/// Joins the records of the right stream with the retained records of the
+/// left stream. See Join::bounded_left_join for the details.
+///
+/// This is the common implementation of the left joins, which decide on the
+/// parallelization contracts.
+fn retained_join<G, D1, D2, D3, H, P1, P2, K1, K2, J>(left: &Stream<G, D1>, right: &Stream<G, D2>, pact_1: P1, pact_2: P2, retention: JoinRetention, key_1: K1, key_2: K2, joiner: J) -> (Stream<G, D3>, Stream<G, usize>)
+where G: Scope,
+      G::Timestamp: IntegerTimestamp,
+      D1: Data, D2: Data, D3: Data,
+      H: Hash+Eq+Clone+'static,
+      P1: ParallelizationContract<G::Timestamp, D1>,
+      P2: ParallelizationContract<G::Timestamp, D2>,
+      K1: Fn(&D1)->H+'static,
+      K2: Fn(&D2)->H+'static,
+      J: Fn(D1, D2)->D3+'static {
+    let mut d1s = JoinState::new(retention);
+    let mut d2s = JoinState::new(JoinRetention{ ttl: retention.ttl, ..JoinRetention::unbounded() });
+
+    let output = left.binary_notify(right, pact_1, pact_2, "Join", Vec::new(), move |input1, input2, output, notificator| {
+        input1.for_each(|time, data|{
+            let epoch = time.time().to_integer();
+            let mut keys = HashSet::new();
+            data.drain(..).for_each(|d1| {
+                let k1 = key_1(&d1);
+                d1s.insert(k1.clone(), epoch, d1);
+                keys.insert(k1);
+            });
+            // Only join the waiting records once all new records are in.
+            let mut out = output.session(&time);
+            for k1 in keys {
+                if let Some(d2) = d2s.remove(&k1) {
+                    if let Some(d1) = d1s.get(&k1) {
+                        for (_, d2) in d2 {
+                            out.give_iterator(d1.iter().map(|&(_, ref d1)| JoinOutput::Joined(joiner(d1.clone(), d2.clone()))));
+                        }
+                    }
+                }
+            }
+            if let Some(ttl) = retention.ttl {
+                notificator.notify_at(time.delayed(&G::Timestamp::from_integer(epoch + ttl)));
+            }
+            notificator.notify_at(time.retain());
+        });
+
+        input2.for_each(|time, data|{
+            let epoch = time.time().to_integer();
+            let mut out = output.session(&time);
+            data.drain(..).for_each(|d2| {
+                let k2 = key_2(&d2);
+                if let Some(d1) = d1s.get(&k2) {
+                    out.give_iterator(d1.iter().map(|&(_, ref d1)| JoinOutput::Joined(joiner(d1.clone(), d2.clone()))));
+                    return;
+                }
+                d2s.insert(k2, epoch, d2);
+            });
+            if let Some(ttl) = retention.ttl {
+                notificator.notify_at(time.delayed(&G::Timestamp::from_integer(epoch + ttl)));
+            }
+            notificator.notify_at(time.retain());
+        });
+
+        notificator.for_each(|time, _, _| {
+            let epoch = time.time().to_integer();
+            d1s.expire(epoch);
+            d2s.expire(epoch);
+            output.session(&time).give(JoinOutput::StateSize(d1s.len() + d2s.len()));
+        });
+    });
+
+    (output.filter_map(|o| if let JoinOutput::Joined(d) = o { Some(d) } else { None }),
+     output.filter_map(|o| if let JoinOutput::StateSize(s) = o { Some(s) } else { None }))
 }
 
 impl<G: Scope, D1: Data+Send> Join<G, D1> for Stream<G, D1> {
@@ -80,33 +346,271 @@ impl<G: Scope, D1: Data+Send> Join<G, D1> for Stream<G, D1> {
           D2: Data+Send, D3: Data,
           K1: Fn(&D1)->H+'static,
           K2: Fn(&D2)->H+'static,
-          J: Fn(D1, D2)->D3+'static {
-        let mut d1s = HashMap::new();
-        let mut d2s: HashMap<H, Vec<D2>> = HashMap::new();
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        self.bounded_left_join(stream, JoinRetention::unbounded(), key_1, key_2, joiner).0
+    }
+
+    /// Joins every record of the second stream with the records of the
+    /// first stream that share its key.
+    ///
+    /// The records of the first stream are kept as long as the retention
+    /// allows, and every record of the second stream is joined with those
+    /// that are kept when it arrives. Records of the second stream without
+    /// a match are kept until records with their key arrive on the first
+    /// stream, so that they are joined with all of those, or until their
+    /// time to live runs out. The second stream that is returned holds the
+    /// number of records kept on each worker at the end of every epoch.
+    fn bounded_left_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, retention: JoinRetention, key_1: K1, key_2: K2, joiner: J) -> (Stream<G, D3>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        let (key_1, exchange_1) = exchange!(key_1);
+        let (key_2, exchange_2) = exchange!(key_2);
+        retained_join(self, stream, exchange_1, exchange_2, retention, move |d| key_1(d), move |d| key_2(d), joiner)
+    }
+
+    fn broadcast_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, joiner: J) -> Stream<G, D3>
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        self.bounded_broadcast_join(stream, JoinRetention::unbounded(), key_1, key_2, joiner).0
+    }
+
+    /// Joins like bounded_left_join, but replicates the first stream to all
+    /// workers instead of exchanging both streams by key.
+    ///
+    /// This avoids moving the records of the second stream around, and is
+    /// thus much cheaper if the first stream is small in comparison. Every
+    /// worker however keeps a copy of all records of the first stream, and
+    /// reports its state size separately.
+    fn bounded_broadcast_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, retention: JoinRetention, key_1: K1, key_2: K2, joiner: J) -> (Stream<G, D3>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        let peers = self.scope().peers();
+        let replicated = self
+            .flat_map(move |d1| (0..peers).map(move |w| (w, d1.clone())))
+            .unary_stream(Exchange::new(|d: &(usize, D1)| d.0 as u64), "Broadcast", |input, output| {
+                input.for_each(|time, data| {
+                    output.session(&time).give_iterator(data.drain(..).map(|(_, d1)| d1));
+                });
+            });
+        retained_join(&replicated, stream, Pipeline, Pipeline, retention, key_1, key_2, joiner)
+    }
+
+    /// Joins the records of both streams that share a key and whose
+    /// intervals of event time overlap.
+    ///
+    /// The interval functions return the first and last epoch of event time
+    /// that a record covers, which lets every record decide on its own
+    /// interval. For bounds relative to the time of each side, see
+    /// time_interval_join. The watermark follows the input frontier, and
+    /// records are dropped as soon as it passes the end of their interval.
+    /// Records whose interval starts behind the watermark are thus only
+    /// joined with records that are still held.
+    ///
+    /// The second stream that is returned holds the records of the second
+    /// input that were dropped without having been joined with any record,
+    /// and the third the number of records held on each worker at the end
+    /// of every epoch.
+    fn interval_join<H, D2, D3, K1, K2, I1, I2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, interval_1: I1, interval_2: I2, joiner: J) -> (Stream<G, D3>, Stream<G, D2>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          I1: Fn(&D1)->(usize, usize)+'static,
+          I2: Fn(&D2)->(usize, usize)+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        // The records per key, along with their interval and the epoch on
+        // which they are dropped. The second side remembers whether it
+        // has been joined.
+        let mut d1s: HashMap<H, Vec<((usize, usize), usize, D1)>> = HashMap::new();
+        let mut d2s: HashMap<H, Vec<((usize, usize), usize, bool, D2)>> = HashMap::new();
+        // The keys that have records to drop on each epoch.
+        let mut expiring: HashMap<usize, HashSet<H>> = HashMap::new();
+        let mut size = 0;
+        let overlaps = |a: (usize, usize), b: (usize, usize)| a.0 <= b.1 && b.0 <= a.1;
 
         let (key_1, exchange_1) = exchange!(key_1);
         let (key_2, exchange_2) = exchange!(key_2);
 
-        self.binary_notify(stream, exchange_1, exchange_2, "Join", Vec::new(), move |input1, input2, output, _| {
+        let output = self.binary_notify(stream, exchange_1, exchange_2, "IntervalJoin", Vec::new(), move |input1, input2, output, notificator| {
             input1.for_each(|time, data|{
+                let epoch = time.time().to_integer();
+                let mut out = output.session(&time);
                 data.drain(..).for_each(|d1| {
                     let k1 = key_1(&d1);
-                    if let Some(mut d2) = d2s.remove(&k1) {
-                        output.session(&time).give_iterator(d2.drain(..).map(|d| joiner(d1.clone(), d)));
+                    let i1 = interval_1(&d1);
+                    if let Some(waiting) = d2s.get_mut(&k1) {
+                        for &mut (_, _, ref mut joined, ref d2) in waiting.iter_mut().filter(|d2| overlaps(i1, d2.0)) {
+                            *joined = true;
+                            out.give(IntervalJoinOutput::Joined(joiner(d1.clone(), d2.clone())));
+                        }
                     }
-                    d1s.insert(k1, d1);
+                    let until = max(i1.1, epoch);
+                    notificator.notify_at(time.delayed(&G::Timestamp::from_integer(until)));
+                    expiring.entry(until).or_insert_with(HashSet::new).insert(k1.clone());
+                    d1s.entry(k1).or_insert_with(Vec::new).push((i1, until, d1));
+                    size += 1;
                 });
+                notificator.notify_at(time.retain());
             });
 
             input2.for_each(|time, data|{
+                let epoch = time.time().to_integer();
+                let mut out = output.session(&time);
                 data.drain(..).for_each(|d2| {
                     let k2 = key_2(&d2);
-                    if let Some(d1) = d1s.get(&k2) {
-                        output.session(&time).give(joiner(d1.clone(), d2));
-                    } else {
-                        d2s.entry(k2).or_insert_with(Vec::new).push(d2);
+                    let i2 = interval_2(&d2);
+                    let mut joined = false;
+                    if let Some(held) = d1s.get(&k2) {
+                        for &(_, _, ref d1) in held.iter().filter(|d1| overlaps(d1.0, i2)) {
+                            joined = true;
+                            out.give(IntervalJoinOutput::Joined(joiner(d1.clone(), d2.clone())));
+                        }
                     }
+                    let until = max(i2.1, epoch);
+                    notificator.notify_at(time.delayed(&G::Timestamp::from_integer(until)));
+                    expiring.entry(until).or_insert_with(HashSet::new).insert(k2.clone());
+                    d2s.entry(k2).or_insert_with(Vec::new).push((i2, until, joined, d2));
+                    size += 1;
                 });
+                notificator.notify_at(time.retain());
+            });
+
+            notificator.for_each(|time, _, _| {
+                let epoch = time.time().to_integer();
+                let mut out = output.session(&time);
+                for key in expiring.remove(&epoch).unwrap_or_else(HashSet::new) {
+                    if let Some(mut d1) = d1s.remove(&key) {
+                        size -= d1.len();
+                        d1.retain(|d1| epoch < d1.1);
+                        size += d1.len();
+                        if !d1.is_empty() { d1s.insert(key.clone(), d1); }
+                    }
+                    if let Some(d2) = d2s.remove(&key) {
+                        let (expired, kept): (Vec<_>, Vec<_>) = d2.into_iter().partition(|d2| d2.1 <= epoch);
+                        size -= expired.len();
+                        out.give_iterator(expired.into_iter().filter(|d2| !d2.2).map(|d2| IntervalJoinOutput::Unmatched(d2.3)));
+                        if !kept.is_empty() { d2s.insert(key, kept); }
+                    }
+                }
+                out.give(IntervalJoinOutput::StateSize(size));
+            });
+        });
+
+        (output.filter_map(|o| if let IntervalJoinOutput::Joined(d) = o { Some(d) } else { None }),
+         output.filter_map(|o| if let IntervalJoinOutput::Unmatched(d) = o { Some(d) } else { None }),
+         output.filter_map(|o| if let IntervalJoinOutput::StateSize(s) = o { Some(s) } else { None }))
+    }
+
+    /// Joins the records of both streams that share a key and whose times
+    /// t1 and t2 lie within the given bounds of each other, that is
+    /// t1-before <= t2 <= t1+after.
+    ///
+    /// The time functions return the event time of a record in epochs. This
+    /// is an interval join in which the first side covers (t1, t1+after) and
+    /// the second (t2, t2+before), so that records are held for as long as
+    /// a match may still arrive. See interval_join for the returned streams.
+    fn time_interval_join<H, D2, D3, K1, K2, T1, T2, J>(&self, stream: &Stream<G, D2>, key_1: K1, key_2: K2, time_1: T1, time_2: T2, before: usize, after: usize, joiner: J) -> (Stream<G, D3>, Stream<G, D2>, Stream<G, usize>)
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          T1: Fn(&D1)->usize+'static,
+          T2: Fn(&D2)->usize+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        self.interval_join(stream, key_1, key_2,
+                           move |d1| { let t = time_1(d1); (t, t + after) },
+                           move |d2| { let t = time_2(d2); (t, t + before) },
+                           joiner)
+    }
+
+    /// Joins the records of both streams that share a key and a window.
+    ///
+    /// This is equivalent to epoch_window on both streams followed by
+    /// epoch_join, but the records are only kept once. Both sides are held
+    /// in panes of the greatest common divisor of size and slide epochs, and
+    /// once a window is complete, the records of its panes are joined. The
+    /// panes are dropped as soon as no later window needs them.
+    ///
+    /// Windows start at multiples of the slide and are emitted on their last
+    /// epoch. A tumbling window is one whose slide equals its size.
+    fn window_join<H, D2, D3, K1, K2, J>(&self, stream: &Stream<G, D2>, size: usize, slide: usize, key_1: K1, key_2: K2, joiner: J) -> Stream<G, D3>
+    where H: Hash+Eq+Data+Clone,
+          D2: Data+Send, D3: Data,
+          K1: Fn(&D1)->H+'static,
+          K2: Fn(&D2)->H+'static,
+          J: Fn(D1, D2)->D3+'static,
+          G::Timestamp: IntegerTimestamp {
+        assert!(0 < size && 0 < slide, "Windows need a positive size and slide.");
+        let pane = gcd(size, slide);
+        let mut panes: HashMap<usize, HashMap<H, (Vec<D1>, Vec<D2>)>> = HashMap::new();
+
+        let (key_1, exchange_1) = exchange!(key_1);
+        let (key_2, exchange_2) = exchange!(key_2);
+
+        self.binary_notify(stream, exchange_1, exchange_2, "WindowJoin", Vec::new(), move |input1, input2, output, notificator| {
+            input1.for_each(|time, data|{
+                let start = time.time().to_integer() / pane * pane;
+                let keys = panes.entry(start).or_insert_with(|| {
+                    for end in window_ends(start, size, slide) {
+                        notificator.notify_at(time.delayed(&G::Timestamp::from_integer(end)));
+                    }
+                    HashMap::new()
+                });
+                data.drain(..).for_each(|d1| {
+                    keys.entry(key_1(&d1)).or_insert_with(|| (Vec::new(), Vec::new())).0.push(d1);
+                });
+            });
+
+            input2.for_each(|time, data|{
+                let start = time.time().to_integer() / pane * pane;
+                let keys = panes.entry(start).or_insert_with(|| {
+                    for end in window_ends(start, size, slide) {
+                        notificator.notify_at(time.delayed(&G::Timestamp::from_integer(end)));
+                    }
+                    HashMap::new()
+                });
+                data.drain(..).for_each(|d2| {
+                    keys.entry(key_2(&d2)).or_insert_with(|| (Vec::new(), Vec::new())).1.push(d2);
+                });
+            });
+
+            notificator.for_each(|time, _, _| {
+                let start = time.time().to_integer() + 1 - size;
+                {
+                    let mut window: HashMap<&H, (Vec<&D1>, Vec<&D2>)> = HashMap::new();
+                    for p in (start..start+size).filter(|p| p % pane == 0) {
+                        if let Some(keys) = panes.get(&p) {
+                            for (k, &(ref d1s, ref d2s)) in keys.iter() {
+                                let entry = window.entry(k).or_insert_with(|| (Vec::new(), Vec::new()));
+                                entry.0.extend(d1s.iter());
+                                entry.1.extend(d2s.iter());
+                            }
+                        }
+                    }
+                    let mut out = output.session(&time);
+                    for (_, (d1s, d2s)) in window {
+                        for d1 in d1s {
+                            out.give_iterator(d2s.iter().map(|&d2| joiner(d1.clone(), d2.clone())));
+                        }
+                    }
+                }
+                // No later window needs the panes before the next window's start.
+                panes.retain(|&p, _| start + slide <= p);
             });
         })
     }
@@ -116,9 +620,12 @@ impl<G: Scope, D1: Data+Send> Join<G, D1> for Stream<G, D1> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::{Arc, Mutex};
     use timely;
-    use timely::dataflow::operators::{ToStream, Capture};
+    use timely::Configuration;
+    use timely::dataflow::operators::{ToStream, Capture, Delay, Inspect};
     use timely::dataflow::operators::capture::Extract;
+    use timely::progress::timestamp::RootTimestamp;
     
     #[test]
     fn epoch_join() {
@@ -151,4 +658,165 @@ mod tests {
 
         assert_eq!(data.extract()[0].1, vec!(0, 1, 2));
     }
+
+    #[test]
+    fn broadcast_join() {
+        let data = timely::example(|scope| {
+            let rhs = vec!((1, 0), (2, 1), (1, 2), (3, 3))
+                .to_stream(scope);
+            vec!((1, 10), (2, 20), (1, 11))
+                .to_stream(scope)
+                .broadcast_join(&rhs, |x| x.0, |y| y.0, |x, y| (x.1, y.1))
+                .capture()
+        });
+
+        assert_eq!(data.extract()[0].1, vec!((10, 0), (10, 2), (11, 0), (11, 2), (20, 1)));
+    }
+
+    #[test]
+    fn broadcast_join_workers() {
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let shared = results.clone();
+        timely::execute(Configuration::Process(2), move |worker| {
+            let index = worker.index();
+            let results = shared.clone();
+            worker.dataflow::<u64, _, _>(move |scope| {
+                // The left records all start out on the first worker, while
+                // every worker has right records of its own.
+                let lhs = if index == 0 { vec!((1, 10), (2, 20), (1, 11)) } else { vec!() };
+                let rhs = if index == 0 { vec!((1, 0), (2, 1)) } else { vec!((1, 2), (3, 3)) };
+                lhs.to_stream(scope)
+                    .broadcast_join(&rhs.to_stream(scope), |x| x.0, |y| y.0, |x, y| (x.1, y.1))
+                    .inspect(move |d| results.lock().unwrap().push(*d));
+            });
+        }).unwrap().join().into_iter().for_each(|r| r.unwrap());
+
+        // Each right record is joined on its own worker only, and thus once.
+        let mut results = results.lock().unwrap().clone();
+        results.sort();
+        assert_eq!(results, vec!((10, 0), (10, 2), (11, 0), (11, 2), (20, 1)));
+    }
+
+    #[test]
+    fn bounded_left_join() {
+        let retention = JoinRetention{ ttl: Some(2), ..JoinRetention::unbounded() };
+        let (data, sizes) = timely::example(move |scope| {
+            let rhs = vec!((1, 1, 20), (3, 1, 21))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0));
+            let (data, sizes) = vec!((0, 1, 10), (0, 1, 11))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .bounded_left_join(&rhs, retention, |x| x.1, |y| y.1, |x, y| (x.2, y.2));
+            (data.capture(), sizes.capture())
+        });
+
+        // The second right record arrives after the left ones expired.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(1), vec!((10, 20), (11, 20)))));
+        assert_eq!(sizes.extract(), vec!((RootTimestamp::new(0), vec!(2)),
+                                         (RootTimestamp::new(1), vec!(2)),
+                                         (RootTimestamp::new(2), vec!(0)),
+                                         (RootTimestamp::new(3), vec!(1)),
+                                         (RootTimestamp::new(5), vec!(0))));
+
+        let retention = JoinRetention{ max_per_key: Some(1), capacity: Some(1), ..JoinRetention::unbounded() };
+        let data = timely::example(move |scope| {
+            let rhs = vec!((2, 1, 20), (2, 2, 21))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0));
+            vec!((0, 1, 10), (0, 1, 11), (1, 2, 12))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .bounded_left_join(&rhs, retention, |x| x.1, |y| y.1, |x, y| (x.2, y.2))
+                .0
+                .capture()
+        });
+
+        // The key 2 pushes out the key 1, which only kept its last record.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(2), vec!((12, 21)))));
+
+        let retention = JoinRetention{ max_per_key: Some(1), capacity: Some(1), ..JoinRetention::unbounded() };
+        let data = timely::example(move |scope| {
+            let rhs = vec!((0, 1, 20), (0, 1, 21), (0, 2, 22), (1, 1, 23))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0));
+            vec!((2, 1, 10))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .bounded_left_join(&rhs, retention, |x| x.1, |y| y.1, |x, y| (x.2, y.2))
+                .0
+                .capture()
+        });
+
+        // The limits do not apply to the right records waiting for their match.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(2), vec!((10, 20), (10, 21), (10, 23)))));
+    }
+
+    #[test]
+    fn window_join() {
+        let data = timely::example(|scope| {
+            let rhs = vec!((1, 1, 20), (3, 1, 21), (3, 2, 22))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0));
+            vec!((0, 1, 10), (1, 1, 11), (2, 1, 12))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .window_join(&rhs, 2, 1, |x| x.1, |y| y.1, |x, y| (x.2, y.2))
+                .capture()
+        });
+
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(1), vec!((10, 20), (11, 20))),
+                                        (RootTimestamp::new(2), vec!((11, 20), (12, 20))),
+                                        (RootTimestamp::new(3), vec!((12, 21)))));
+    }
+
+    #[test]
+    fn interval_join() {
+        let (data, unmatched, sizes) = timely::example(|scope| {
+            let rhs = vec!((0, 1, 20), (2, 1, 21), (3, 1, 22), (4, 2, 23), (7, 2, 24))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0));
+            let (data, unmatched, sizes) = vec!((1, 1, 10), (4, 2, 11))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .interval_join(&rhs, |x| x.1, |y| y.1,
+                               |x| (x.0 as usize, x.0 as usize + 1),
+                               |y| (y.0 as usize, y.0 as usize + 1),
+                               |x, y| (x.2, y.2));
+            (data.capture(), unmatched.capture(), sizes.capture())
+        });
+
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(1), vec!((10, 20))),
+                                        (RootTimestamp::new(2), vec!((10, 21))),
+                                        (RootTimestamp::new(4), vec!((11, 23)))));
+        assert_eq!(unmatched.extract(), vec!((RootTimestamp::new(4), vec!((3, 1, 22))),
+                                             (RootTimestamp::new(8), vec!((7, 2, 24)))));
+        assert_eq!(sizes.extract(), vec!((RootTimestamp::new(0), vec!(1)),
+                                         (RootTimestamp::new(1), vec!(1)),
+                                         (RootTimestamp::new(2), vec!(1)),
+                                         (RootTimestamp::new(3), vec!(1)),
+                                         (RootTimestamp::new(4), vec!(2)),
+                                         (RootTimestamp::new(5), vec!(0)),
+                                         (RootTimestamp::new(7), vec!(1)),
+                                         (RootTimestamp::new(8), vec!(0))));
+    }
+
+    #[test]
+    fn time_interval_join() {
+        let data = timely::example(|scope| {
+            let rhs = vec!((0, 1, 20), (2, 1, 21), (5, 1, 22), (6, 1, 23))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0));
+            vec!((3, 1, 10))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .time_interval_join(&rhs, |x| x.1, |y| y.1, |x| x.0 as usize, |y| y.0 as usize, 1, 2, |x, y| (x.2, y.2))
+                .0
+                .capture()
+        });
+
+        // Only the right records from one epoch before to two after match.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(3), vec!((10, 21))),
+                                        (RootTimestamp::new(5), vec!((10, 22)))));
+    }
 }
@@ -0,0 +1,65 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+use timely::Data;
+use timely::dataflow::channels::pact::Exchange;
+use timely::dataflow::operators::Unary;
+use timely::dataflow::{Stream, Scope};
+
+pub trait LastN<G: Scope, D: Data+Send> {
+    fn last_n<K, H, A, DO>(&self, size: usize, key: K, aggregator: A) -> Stream<G, DO>
+    where K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          A: Fn(&H, &VecDeque<D>)->DO+'static,
+          DO: Data;
+}
+
+impl<G: Scope, D: Data+Send> LastN<G, D> for Stream<G, D> {
+    /// Aggregates over the last records of each key.
+    ///
+    /// Every record is added to the records of its key, of which only the
+    /// last size ones are kept, and the aggregate of those is emitted. Unlike
+    /// partition this thus emits an updated result for every record, even
+    /// before the key has seen size records.
+    fn last_n<K, H, A, DO>(&self, size: usize, key: K, aggregator: A) -> Stream<G, DO>
+    where K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          A: Fn(&H, &VecDeque<D>)->DO+'static,
+          DO: Data {
+        let mut lasts = HashMap::new();
+
+        let (key, exchange) = exchange!(key);
+
+        self.unary_stream(exchange, "LastN", move |input, output| {
+            input.for_each(|time, data| {
+                output.session(&time).give_iterator(data.drain(..).map(|dat| {
+                    let key = key(&dat);
+                    let last = lasts.entry(key.clone()).or_insert_with(|| VecDeque::with_capacity(size));
+                    if last.len() == size { last.pop_front(); }
+                    last.push_back(dat);
+                    aggregator(&key, last)
+                }));
+            });
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timely;
+    use timely::dataflow::operators::{ToStream, Capture};
+    use timely::dataflow::operators::capture::Extract;
+
+    #[test]
+    fn last_n() {
+        let data = timely::example(|scope| {
+            (1..7).to_stream(scope)
+                .last_n(2, |x| x % 2, |&k, l| (k, l.iter().sum::<i32>()))
+                .capture()
+        });
+
+        assert_eq!(data.extract()[0].1, vec!((0, 2), (0, 6), (0, 10),
+                                             (1, 1), (1, 4), (1, 8)));
+    }
+}
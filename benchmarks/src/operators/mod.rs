@@ -80,18 +80,30 @@ integer_timestampify!(u32);
 integer_timestampify!(u64);
 integer_timestampify!(usize);
 
+/// Returns the greatest common divisor, which is the size of the panes that
+/// sliding windows of the given size and slide are made up of.
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
 pub use self::filtermap::FilterMap;
-pub use self::join::Join;
+pub use self::join::{Join, JoinRetention};
+pub use self::lastn::LastN;
 pub use self::partition::Partition;
 pub use self::reduce::Reduce;
 pub use self::rollingcount::RollingCount;
 pub use self::session::Session;
+pub use self::topk::TopK;
+pub use self::trigger::{Trigger, Accumulation, PaneKind};
 pub use self::window::Window;
 
 pub mod filtermap;
 pub mod join;
+pub mod lastn;
 pub mod partition;
 pub mod reduce;
 pub mod rollingcount;
 pub mod session;
+pub mod topk;
+pub mod trigger;
 pub mod window;
@@ -6,6 +6,8 @@ use timely::Data;
 use timely::dataflow::channels::pact::Exchange;
 use timely::dataflow::operators::generic::Unary;
 use timely::dataflow::{Stream, Scope};
+use super::{IntegerTimestamp, gcd};
+use super::trigger::{self, Trigger, PaneKind};
 
 #[inline]
 fn max<V: PartialOrd>(a: V, b: V) -> V {
@@ -52,6 +54,23 @@ pub trait Reduce<G: Scope, D: Data+Send> {
     fn reduce_to<V, R>(&self, initial_value: V, reductor: R) -> Stream<G, V>
     where V: Eq+Data+Clone,
           R: Fn(D, V)->V+'static;
+
+    fn window_reduce_by<H, V, K, R, M>(&self, size: usize, slide: usize, key_extractor: K, initial_value: V, reductor: R, merger: M) -> Stream<G, (H, V)>
+    where H: Hash+Eq+Data+Clone,
+          V: Eq+Data+Clone,
+          K: Fn(&D)->H+'static,
+          R: Fn(D, V)->V+'static,
+          M: Fn(V, V)->V+'static,
+          G::Timestamp: IntegerTimestamp;
+
+    fn triggered_window_reduce_by<H, V, T, K, R, M>(&self, size: usize, slide: usize, trigger: Trigger, time: T, key_extractor: K, initial_value: V, reductor: R, merger: M) -> Stream<G, ((H, usize), PaneKind, V)>
+    where H: Hash+Eq+Data+Clone,
+          V: Eq+Data+Clone,
+          T: Fn(&D)->usize+'static,
+          K: Fn(&D)->H+'static,
+          R: Fn(D, V)->V+'static,
+          M: Fn(V, V)->V+'static,
+          G::Timestamp: IntegerTimestamp;
 }
 
 impl<G: Scope, D: Data+Send> Reduce<G, D> for Stream<G, D> {
@@ -150,16 +169,97 @@ impl<G: Scope, D: Data+Send> Reduce<G, D> for Stream<G, D> {
             });
         })
     }
-}
 
+    /// Reduces the records per key over sliding windows of epochs.
+    ///
+    /// This is equivalent to epoch_window followed by reduce_by, but rather
+    /// than buffering the records of a window, the operator only keeps the
+    /// reduced value per key for each pane. A pane spans the greatest common
+    /// divisor of size and slide epochs, so that every window is made up of
+    /// whole panes, whose values are combined with the merger once the window
+    /// is complete. The merger must thus be associative.
+    ///
+    /// Windows start at multiples of the slide and are emitted on their last
+    /// epoch. A tumbling window is one whose slide equals its size.
+    fn window_reduce_by<H, V, K, R, M>(&self, size: usize, slide: usize, key_extractor: K, initial_value: V, reductor: R, merger: M) -> Stream<G, (H, V)>
+    where H: Hash+Eq+Data+Clone,
+          V: Eq+Data+Clone,
+          K: Fn(&D)->H+'static,
+          R: Fn(D, V)->V+'static,
+          M: Fn(V, V)->V+'static,
+          G::Timestamp: IntegerTimestamp {
+        let pane = gcd(size, slide);
+        let mut panes: HashMap<usize, HashMap<H, V>> = HashMap::new();
+
+        let (key, exchange) = exchange!(key_extractor);
+
+        self.unary_notify(exchange, "WindowReduce", Vec::new(), move |input, output, notificator| {
+            input.for_each(|time, data| {
+                let start = time.time().to_integer() / pane * pane;
+                let values = panes.entry(start).or_insert_with(|| {
+                    // Notify at the end of every window the new pane is part of.
+                    let first = if size <= start { (start - size) / slide + 1 } else { 0 };
+                    for k in first..start/slide+1 {
+                        notificator.notify_at(time.delayed(&G::Timestamp::from_integer(k * slide + size - 1)));
+                    }
+                    HashMap::new()
+                });
+                data.drain(..).for_each(|dat| {
+                    let key = key(&dat);
+                    let value = values.remove(&key).unwrap_or_else(|| initial_value.clone());
+                    values.insert(key, reductor(dat, value));
+                });
+            });
+            notificator.for_each(|time, _, _| {
+                let start = time.time().to_integer() + 1 - size;
+                let mut window: HashMap<H, V> = HashMap::new();
+                for p in (start..start+size).filter(|p| p % pane == 0) {
+                    if let Some(values) = panes.get(&p) {
+                        for (k, v) in values.iter() {
+                            let merged = match window.remove(k) {
+                                Some(w) => merger(w, v.clone()),
+                                None => v.clone()
+                            };
+                            window.insert(k.clone(), merged);
+                        }
+                    }
+                }
+                // No later window needs the panes before the next window's start.
+                panes.retain(|&p, _| start + slide <= p);
+                output.session(&time).give_iterator(window.drain());
+            });
+        })
+    }
+
+    /// Reduces the records per key over sliding windows of event time that
+    /// fire according to the trigger.
+    ///
+    /// Every result is emitted along with its key, the window's start, and
+    /// the kind of firing it stems from. The merger combines the results of
+    /// successive firings and must thus be associative, with the initial
+    /// value being neutral to it. See trigger::triggered_windows for details.
+    fn triggered_window_reduce_by<H, V, T, K, R, M>(&self, size: usize, slide: usize, trigger: Trigger, time: T, key_extractor: K, initial_value: V, reductor: R, merger: M) -> Stream<G, ((H, usize), PaneKind, V)>
+    where H: Hash+Eq+Data+Clone,
+          V: Eq+Data+Clone,
+          T: Fn(&D)->usize+'static,
+          K: Fn(&D)->H+'static,
+          R: Fn(D, V)->V+'static,
+          M: Fn(V, V)->V+'static,
+          G::Timestamp: IntegerTimestamp {
+        let (key, exchange) = exchange!(key_extractor);
+        trigger::triggered_windows(self, exchange, size, slide, trigger, time, move |d| key(d), initial_value, reductor, merger)
+    }
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use timely;
-    use timely::dataflow::operators::{ToStream, Capture};
+    use timely::dataflow::operators::{ToStream, Capture, Delay, Map};
     use timely::dataflow::operators::capture::Event;
+    use timely::progress::timestamp::RootTimestamp;
     use std::cmp::max;
+    use operators::{Trigger, Accumulation, PaneKind};
 
     //// Copypasta from timely/dataflow/operators/capture/extract.rs.html
     pub trait Extract<T: PartialOrd, D: PartialOrd> {
@@ -267,4 +367,37 @@ mod tests {
         
         assert_eq!(data.extract()[0].1, vec!(10));
     }
+
+    #[test]
+    fn window_reduce_by() {
+        let data = timely::example(|scope| {
+            vec!((0, 1), (1, 2), (2, 3), (3, 4), (4, 5), (5, 6))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .map(|d| d.1)
+                .window_reduce_by(4, 2, |x| x%2, 0, |x, s| s+x, |a, b| a+b)
+                .capture()
+        });
+
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(3), vec!((0, 6), (1, 4))),
+                                        (RootTimestamp::new(5), vec!((0, 10), (1, 8))),
+                                        (RootTimestamp::new(7), vec!((0, 6), (1, 5)))));
+    }
+
+    #[test]
+    fn triggered_window_reduce_by() {
+        let data = timely::example(|scope| {
+            let trigger = Trigger{ early_epochs: Some(1), accumulation: Accumulation::Retracting, ..Trigger::on_time() };
+            vec!((0, 1), (0, 3), (1, 5), (1, 2))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .triggered_window_reduce_by(2, 2, trigger, |d| d.0 as usize, |d| d.1%2, 0, |d, s| s+d.1, |a, b| a+b)
+                .capture()
+        });
+
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(0), vec!(((1, 0), PaneKind::Early, 4))),
+                                        (RootTimestamp::new(1), vec!(((0, 0), PaneKind::OnTime, 2),
+                                                                     ((1, 0), PaneKind::OnTime, 9),
+                                                                     ((1, 0), PaneKind::Retraction, 4)))));
+    }
 }
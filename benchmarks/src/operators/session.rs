@@ -1,5 +1,7 @@
-use std::collections::{HashMap};
+use std::cmp::{max, min};
+use std::collections::{HashMap, HashSet};
 use std::hash::{Hash, Hasher};
+use std::mem;
 use std::rc::Rc;
 use timely::Data;
 use timely::dataflow::channels::pact::Exchange;
@@ -12,6 +14,43 @@ pub trait Session<G: Scope, D: Data+Send> {
     where W: Fn(&D)->(H, usize)+'static,
           H: Hash+Eq+Data+Clone,
           G::Timestamp: IntegerTimestamp+Hash;
+
+    fn limited_session<W, H>(&self, timeout: usize, max_duration: usize, sessioner: W) -> Stream<G, (H, Vec<D>)>
+    where W: Fn(&D)->(H, usize)+'static,
+          H: Hash+Eq+Data+Clone,
+          G::Timestamp: IntegerTimestamp+Hash;
+}
+
+/// An open session of one key, spanning the times of its first and last record.
+struct Interval<D> {
+    first: usize,
+    last: usize,
+    /// The records of the session along with their times, sorted by time.
+    data: Vec<(usize, D)>,
+}
+
+impl<D> Interval<D> {
+    /// Whether the other session lies within the timeout of this one.
+    fn overlaps(&self, other: &Interval<D>, timeout: usize) -> bool {
+        self.first <= other.last + timeout && other.first <= self.last + timeout
+    }
+
+    /// Merges the other, earlier session into this one.
+    ///
+    /// Records with the same time keep their order of arrival.
+    fn absorb(&mut self, other: Interval<D>) {
+        self.first = min(self.first, other.first);
+        self.last = max(self.last, other.last);
+        let ours = mem::replace(&mut self.data, Vec::new());
+        let mut ours = ours.into_iter().peekable();
+        for (t, d) in other.data {
+            while ours.peek().map_or(false, |&(o, _)| o < t) {
+                self.data.push(ours.next().unwrap());
+            }
+            self.data.push((t, d));
+        }
+        self.data.extend(ours);
+    }
 }
 
 impl<G: Scope, D: Data+Send> Session<G, D> for Stream<G, D> {
@@ -19,53 +58,77 @@ impl<G: Scope, D: Data+Send> Session<G, D> for Stream<G, D> {
     where W: Fn(&D)->(H, usize)+'static,
           H: Hash+Eq+Data+Clone,
           G::Timestamp: IntegerTimestamp+Hash {
-        let mut sessions = HashMap::new();
+        self.limited_session(timeout, usize::max_value(), sessioner)
+    }
+
+    /// Groups the records into sessions per key.
+    ///
+    /// The sessioner returns the key and the time of a record in epochs. A
+    /// session contains all records of a key that are at most timeout epochs
+    /// apart from the next, and is emitted in order of time once the input
+    /// frontier passes its last time plus the timeout. Records may arrive
+    /// out of order, in which case they can join or merge open sessions.
+    /// Records are not added to a session if it would then span more than
+    /// max_duration epochs, and start a separate session instead.
+    fn limited_session<W, H>(&self, timeout: usize, max_duration: usize, sessioner: W) -> Stream<G, (H, Vec<D>)>
+    where W: Fn(&D)->(H, usize)+'static,
+          H: Hash+Eq+Data+Clone,
+          G::Timestamp: IntegerTimestamp+Hash {
+        let mut sessions: HashMap<H, Vec<Interval<D>>> = HashMap::new();
+        // The keys that may have sessions to close on each epoch.
+        let mut closing: HashMap<usize, HashSet<H>> = HashMap::new();
 
         let (key, exchange) = exchange!(sessioner, |(s, _)| s);
-        
+
         self.unary_notify(exchange, "Session", Vec::new(), move |input, output, notificator| {
             input.for_each(|cap, data| {
+                let epoch = cap.time().to_integer();
                 for data in data.drain(..){
                     let (s, t) = key(&data);
-                    notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(t + timeout)));
-                    let session = sessions
-                        .entry(t).or_insert_with(HashMap::new)
-                        .entry(s).or_insert_with(Vec::new);
-                    session.push(data);
+                    let intervals = sessions.entry(s.clone()).or_insert_with(Vec::new);
+                    let mut session = Interval{ first: t, last: t, data: vec!((t, data)) };
+                    // Absorb all sessions that this one now connects, as long
+                    // as the merged session does not get too long.
+                    let mut i = 0;
+                    while i < intervals.len() {
+                        let fits = max(session.last, intervals[i].last) - min(session.first, intervals[i].first) <= max_duration;
+                        if fits && session.overlaps(&intervals[i], timeout) {
+                            session.absorb(intervals.swap_remove(i));
+                            i = 0;
+                        } else {
+                            i += 1;
+                        }
+                    }
+                    let end = max(session.last + timeout, epoch);
+                    intervals.push(session);
+                    notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(end)));
+                    closing.entry(end).or_insert_with(HashSet::new).insert(s);
                 };
             });
-            
+
             notificator.for_each(|cap, _, _| {
-                // For each session at the original time we need to check if it
-                // has expired, or if we need to delay.
-                let otime = cap.time().to_integer() - timeout;
-                let mut expired = sessions.remove(&otime).unwrap_or_else(HashMap::new);
-                expired.drain().for_each(|(s, mut d)| {
-                    // Now we check backwards from the currently expired epoch.
-                    let mut found = false;
-                    for i in 0..timeout {
-                        let t = cap.time().to_integer() - i;
-                        if let Some(session) = sessions.get_mut(&t) {
-                            if let Some(data) = session.get_mut(&s) {
-                                // If we find data within the timeout, delay our data to
-                                // that later time. If that time does not happen to be
-                                // final either, both this and that data will get moved
-                                // ahead even further automatically.
-                                
-                                // FIXME: This causes events to be out of order.
-                                //        That may or may not be important.
-                                data.append(&mut d);
-                                found = true;
-                                break;
+                let epoch = cap.time().to_integer();
+                let mut out = output.session(&cap);
+                for s in closing.remove(&epoch).unwrap_or_else(HashSet::new) {
+                    let mut closed = Vec::new();
+                    if let Some(intervals) = sessions.get_mut(&s) {
+                        let mut i = 0;
+                        while i < intervals.len() {
+                            if intervals[i].last + timeout <= epoch {
+                                closed.push(intervals.swap_remove(i));
+                            } else {
+                                i += 1;
                             }
                         }
                     }
-                    if !found {
-                        // If we don't find a any data within the timeout, the
-                        // session is full and we can output it.
-                        output.session(&cap).give((s, d));
+                    if sessions.get(&s).map_or(false, |i| i.is_empty()) {
+                        sessions.remove(&s);
+                    }
+                    closed.sort_by_key(|session| session.first);
+                    for session in closed {
+                        out.give((s.clone(), session.data.into_iter().map(|(_, d)| d).collect()));
                     }
-                });
+                }
             });
         })
     }
@@ -78,7 +141,7 @@ mod tests {
     use timely::dataflow::operators::{ToStream, Capture, Delay, Map};
     use timely::dataflow::operators::capture::Extract;
     use timely::progress::timestamp::RootTimestamp;
-    
+
     #[test]
     fn session() {
         let data = timely::example(|scope| {
@@ -95,7 +158,38 @@ mod tests {
 
         let data = data.extract();
         assert_eq!(data[0].1, vec!(vec!(2)));
-        assert_eq!(data[1].1, vec!(vec!(5, 3, 1)));
+        assert_eq!(data[1].1, vec!(vec!(1, 3, 5)));
         assert_eq!(data[2].1, vec!(vec!(6)));
     }
+
+    #[test]
+    fn session_merge() {
+        // The record at time 2 arrives late and joins the two sessions.
+        let data = timely::example(|scope| {
+            vec!((0, 0), (0, 4),
+                 (1, 2))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .session(2, |x| (0, x.1 as usize))
+                .map(|x| x.1.iter().map(|x| x.1).collect::<Vec<_>>())
+                .capture()
+        });
+
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(6), vec!(vec!(0, 2, 4)))));
+    }
+
+    #[test]
+    fn limited_session() {
+        let data = timely::example(|scope| {
+            vec!((0, 0), (2, 2), (4, 4), (6, 6))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .limited_session(2, 3, |x| (0, x.1 as usize))
+                .map(|x| x.1.iter().map(|x| x.1).collect::<Vec<_>>())
+                .capture()
+        });
+
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(4), vec!(vec!(0, 2))),
+                                        (RootTimestamp::new(8), vec!(vec!(4, 6)))));
+    }
 }
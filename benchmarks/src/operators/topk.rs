@@ -0,0 +1,160 @@
+use std::cmp::max;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+use timely::Data;
+use timely::dataflow::channels::pact::{Pipeline, Exchange};
+use timely::dataflow::operators::{Map, Unary, Binary};
+use timely::dataflow::{Stream, Scope};
+
+pub trait TopK<G: Scope, D: Data> {
+    fn top_k<V, R>(&self, k: usize, fanout: usize, value: R) -> Stream<G, D>
+    where V: Ord+Data,
+          R: Fn(&D)->V+'static,
+          G::Timestamp: Hash;
+
+    fn arg_max<V, R>(&self, fanout: usize, value: R) -> Stream<G, D>
+    where V: Ord+Data,
+          R: Fn(&D)->V+'static,
+          G::Timestamp: Hash;
+}
+
+/// Merges the values into the k greatest ones seen so far, sorted descendingly.
+fn merge<V: Ord>(top: &mut Vec<V>, values: &mut Vec<V>, k: usize) {
+    top.append(values);
+    top.sort_by(|a, b| b.cmp(a));
+    top.truncate(k);
+}
+
+impl<G: Scope, D: Data> TopK<G, D> for Stream<G, D> {
+    /// Emits the records whose value is among the k greatest of the epoch.
+    ///
+    /// Rather than gathering all records on a single worker, only the values
+    /// travel: each worker first reduces its own records to their k greatest
+    /// values, which are then combined in a tree of the given fanout up to
+    /// worker 0. The resulting k-th value is broadcast to all workers, which
+    /// then emit their local records that reach it. The records thus stay on
+    /// the worker they were on, and ties with the k-th value are all emitted.
+    fn top_k<V, R>(&self, k: usize, fanout: usize, value: R) -> Stream<G, D>
+    where V: Ord+Data,
+          R: Fn(&D)->V+'static,
+          G::Timestamp: Hash {
+        let value = Rc::new(value);
+        let value_ = value.clone();
+        let index = self.scope().index();
+        let peers = self.scope().peers();
+        let fanout = max(fanout, 2);
+
+        // Reduce the values on each worker first.
+        let mut epochs = HashMap::new();
+        let mut top = self.unary_notify(Pipeline, "TopKLocal", Vec::new(), move |input, output, notificator| {
+            input.for_each(|time, data| {
+                let mut values = data.drain(..).map(|d| value_(&d)).collect();
+                merge(epochs.entry(time.time().clone()).or_insert_with(Vec::new), &mut values, k);
+                notificator.notify_at(time.retain());
+            });
+            notificator.for_each(|time, _, _| {
+                if let Some(top) = epochs.remove(time.time()) {
+                    output.session(&time).give(top);
+                }
+            });
+        });
+
+        // Combine the candidates in a tree. At every level, each group of
+        // fanout workers sends its candidates to the first worker of the group.
+        let mut stride = 1;
+        while stride < peers {
+            stride *= fanout;
+            let target = index / stride * stride;
+            let mut epochs = HashMap::new();
+            top = top
+                .map(move |top| (target, top))
+                .unary_notify(Exchange::new(|d: &(usize, Vec<V>)| d.0 as u64), "TopKCombine", Vec::new(), move |input, output, notificator| {
+                    input.for_each(|time, data| {
+                        let top = epochs.entry(time.time().clone()).or_insert_with(Vec::new);
+                        for (_, mut values) in data.drain(..) {
+                            merge(top, &mut values, k);
+                        }
+                        notificator.notify_at(time.retain());
+                    });
+                    notificator.for_each(|time, _, _| {
+                        if let Some(top) = epochs.remove(time.time()) {
+                            output.session(&time).give(top);
+                        }
+                    });
+                });
+        }
+
+        // Worker 0 now holds the result, so broadcast the threshold from it.
+        let thresholds = top.flat_map(move |top| {
+            let threshold = top.last().cloned();
+            (0..peers).map(move |w| (w, threshold.clone()))
+        });
+
+        let mut records = HashMap::new();
+        let mut limits = HashMap::new();
+        self.binary_notify(&thresholds, Pipeline, Exchange::new(|d: &(usize, Option<V>)| d.0 as u64), "TopK", Vec::new(), move |input1, input2, output, notificator| {
+            input1.for_each(|time, data| {
+                records.entry(time.time().clone()).or_insert_with(Vec::new).extend(data.drain(..));
+                notificator.notify_at(time.retain());
+            });
+            input2.for_each(|time, data| {
+                for (_, threshold) in data.drain(..) {
+                    limits.insert(time.time().clone(), threshold);
+                }
+                notificator.notify_at(time.retain());
+            });
+            notificator.for_each(|time, _, _| {
+                let limit = limits.remove(time.time());
+                if let (Some(mut records), Some(Some(limit))) = (records.remove(time.time()), limit) {
+                    output.session(&time).give_iterator(records.drain(..).filter(|d| limit <= value(d)));
+                }
+            });
+        })
+    }
+
+    /// Emits the records with the greatest value of the epoch.
+    fn arg_max<V, R>(&self, fanout: usize, value: R) -> Stream<G, D>
+    where V: Ord+Data,
+          R: Fn(&D)->V+'static,
+          G::Timestamp: Hash {
+        self.top_k(1, fanout, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timely;
+    use timely::dataflow::operators::{ToStream, Capture, Delay};
+    use timely::dataflow::operators::capture::Extract;
+    use timely::progress::timestamp::RootTimestamp;
+
+    #[test]
+    fn top_k() {
+        let data = timely::example(|scope| {
+            vec!((0, 5), (0, 3), (0, 5), (0, 1),
+                 (1, 2), (1, 4))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .top_k(3, 2, |d| d.1)
+                .capture()
+        });
+
+        let data = data.extract();
+        assert_eq!(data[0].1, vec!((0, 3), (0, 5), (0, 5)));
+        assert_eq!(data[1].1, vec!((1, 2), (1, 4)));
+    }
+
+    #[test]
+    fn arg_max() {
+        let data = timely::example(|scope| {
+            vec!((1, 5), (2, 3), (3, 5), (4, 1))
+                .to_stream(scope)
+                .arg_max(2, |d| d.1)
+                .capture()
+        });
+
+        assert_eq!(data.extract()[0].1, vec!((1, 5), (3, 5)));
+    }
+}
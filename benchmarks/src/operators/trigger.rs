@@ -0,0 +1,185 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use timely::Data;
+use timely::dataflow::channels::pact::ParallelizationContract;
+use timely::dataflow::operators::Unary;
+use timely::dataflow::{Stream, Scope};
+use super::IntegerTimestamp;
+
+/// The kind of firing that a window result stems from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Abomonation)]
+pub enum PaneKind {
+    /// A speculative result from before the watermark passed the window's end.
+    Early,
+    /// The result from when the watermark passed the window's end.
+    OnTime,
+    /// A refinement due to records that arrived after the on-time result.
+    Late,
+    /// Withdraws the previous result of the window, which the next replaces.
+    Retraction,
+}
+
+/// How the results of successive firings of a window relate to each other.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Accumulation {
+    /// Every result only covers the records since the previous firing.
+    Discarding,
+    /// Every result covers all records of the window so far.
+    Accumulating,
+    /// Like accumulating, but every result is preceded by a retraction of
+    /// the previous one, so that the results can simply be summed up.
+    Retracting,
+}
+
+/// Determines when a window emits its results.
+///
+/// A window always fires once the watermark passes its end. Early firings
+/// give speculative results before that, either every few epochs or once
+/// enough records arrived since the last firing. Records that arrive after
+/// the on-time firing but within the allowed lateness cause a late firing
+/// at the end of their epoch, while records that arrive even later are
+/// dropped.
+#[derive(Clone, Debug)]
+pub struct Trigger {
+    pub early_epochs: Option<usize>,
+    pub early_records: Option<usize>,
+    pub lateness: usize,
+    pub accumulation: Accumulation,
+}
+
+impl Trigger {
+    /// Only fires once per window and drops all late records.
+    pub fn on_time() -> Self {
+        Trigger{ early_epochs: None, early_records: None, lateness: 0, accumulation: Accumulation::Discarding }
+    }
+
+    /// Fires a window, returning the results to emit.
+    ///
+    /// Early and late firings only produce a result if records arrived since
+    /// the previous firing, whereas the on-time firing always does.
+    fn fire<V, M>(&self, state: &mut WindowState<V>, kind: PaneKind, initial: &V, merger: &M) -> Vec<(PaneKind, V)>
+    where V: Clone,
+          M: Fn(V, V)->V {
+        let pending = match state.pending.take() {
+            Some(pending) => pending,
+            None if kind == PaneKind::OnTime => initial.clone(),
+            None => return Vec::new(),
+        };
+        state.records = 0;
+        if self.accumulation == Accumulation::Discarding {
+            return vec!((kind, pending));
+        }
+        let mut results = Vec::new();
+        let total = match state.accumulated.take() {
+            Some(previous) => {
+                if self.accumulation == Accumulation::Retracting {
+                    results.push((PaneKind::Retraction, previous.clone()));
+                }
+                merger(previous, pending)
+            },
+            None => pending
+        };
+        state.accumulated = Some(total.clone());
+        results.push((kind, total));
+        results
+    }
+}
+
+/// The reduced records of a window, split at its last firing.
+struct WindowState<V> {
+    pending: Option<V>,
+    records: usize,
+    accumulated: Option<V>,
+}
+
+/// Reduces the records per key over sliding windows of event time, and
+/// emits the results along with the window start whenever the trigger fires.
+///
+/// The event time of a record is given in epochs by the time function, and
+/// the watermark follows the input frontier. Records are reduced into the
+/// windows that contain them, starting from the initial value, and the
+/// merger combines the results of successive firings if the trigger
+/// accumulates. The initial value thus needs to be neutral to the merger.
+///
+/// This is the common implementation of the triggered window operators,
+/// which decide on the parallelization contract.
+pub fn triggered_windows<G, D, P, H, V, T, K, R, M>(stream: &Stream<G, D>, pact: P, size: usize, slide: usize, trigger: Trigger, time: T, key: K, initial_value: V, reductor: R, merger: M) -> Stream<G, ((H, usize), PaneKind, V)>
+where G: Scope,
+      G::Timestamp: IntegerTimestamp,
+      D: Data,
+      P: ParallelizationContract<G::Timestamp, D>,
+      H: Hash+Eq+Data+Clone,
+      V: Data+Clone,
+      T: Fn(&D)->usize+'static,
+      K: Fn(&D)->H+'static,
+      R: Fn(D, V)->V+'static,
+      M: Fn(V, V)->V+'static {
+    assert!(0 < size && 0 < slide, "Windows need a positive size and slide.");
+    let mut windows: HashMap<(H, usize), WindowState<V>> = HashMap::new();
+    // The windows that may fire on each epoch.
+    let mut due: HashMap<usize, HashSet<(H, usize)>> = HashMap::new();
+    let end = move |start: usize| start + size - 1;
+
+    stream.unary_notify(pact, "TriggeredWindow", Vec::new(), move |input, output, notificator| {
+        input.for_each(|cap, data| {
+            let epoch = cap.time().to_integer();
+            let mut session = output.session(&cap);
+            for data in data.drain(..) {
+                let t = time(&data);
+                let h = key(&data);
+                // The windows containing t start within (t-size, t]. If the
+                // slide exceeds the size, t may not be in any window.
+                let first = if size <= t { (t - size) / slide + 1 } else { 0 };
+                for start in (first..t/slide+1).map(|k| k * slide).filter(|&s| t < s + size) {
+                    if end(start) + trigger.lateness < epoch { continue; }
+                    let window = (h.clone(), start);
+                    let mut firings = Vec::new();
+                    let state = windows.entry(window.clone()).or_insert_with(|| {
+                        if let Some(every) = trigger.early_epochs {
+                            firings.extend((1..).map(|k| start + k * every - 1).take_while(|&e| e < end(start)));
+                        }
+                        firings.push(end(start));
+                        firings.push(end(start) + trigger.lateness);
+                        WindowState{ pending: None, records: 0, accumulated: None }
+                    });
+                    // Late records are fired at the end of their epoch.
+                    if end(start) < epoch { firings.push(epoch); }
+                    for e in firings.into_iter().filter(|&e| epoch <= e) {
+                        notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(e)));
+                        due.entry(e).or_insert_with(HashSet::new).insert(window.clone());
+                    }
+
+                    let value = state.pending.take().unwrap_or_else(|| initial_value.clone());
+                    state.pending = Some(reductor(data.clone(), value));
+                    state.records += 1;
+                    if let Some(every) = trigger.early_records {
+                        if epoch <= end(start) && every <= state.records {
+                            for (kind, value) in trigger.fire(state, PaneKind::Early, &initial_value, &merger) {
+                                session.give((window.clone(), kind, value));
+                            }
+                        }
+                    }
+                }
+            }
+        });
+
+        notificator.for_each(|cap, _, _| {
+            let epoch = cap.time().to_integer();
+            let mut session = output.session(&cap);
+            for window in due.remove(&epoch).unwrap_or_else(HashSet::new) {
+                let kind = if epoch < end(window.1) { PaneKind::Early }
+                    else if epoch == end(window.1) { PaneKind::OnTime }
+                    else { PaneKind::Late };
+                if let Some(state) = windows.get_mut(&window) {
+                    for (kind, value) in trigger.fire(state, kind, &initial_value, &merger) {
+                        session.give((window.clone(), kind, value));
+                    }
+                }
+                // Nothing can arrive for the window anymore.
+                if end(window.1) + trigger.lateness <= epoch {
+                    windows.remove(&window);
+                }
+            }
+        });
+    })
+}
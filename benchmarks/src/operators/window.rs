@@ -1,9 +1,21 @@
-use std::collections::HashMap;
+use clock::Clock;
+use std::cmp::max;
+use std::collections::{HashMap, BTreeSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
 use timely::Data;
-use timely::dataflow::channels::pact::Pipeline;
-use timely::dataflow::operators::Unary;
+use timely::dataflow::channels::pact::{Pipeline, Exchange};
+use timely::dataflow::operators::{Unary, Map};
 use timely::dataflow::{Stream, Scope};
-use super::IntegerTimestamp;
+use super::{IntegerTimestamp, FilterMap};
+use super::trigger::{self, Trigger, PaneKind};
+
+/// The output of the event time windows, before it is split into its streams.
+#[derive(Clone, Abomonation)]
+enum EventTimeOutput<D> {
+    Window(usize, Vec<D>),
+    Late(D),
+}
 
 pub trait Window<G: Scope, D: Data>
 where G::Timestamp: IntegerTimestamp {
@@ -11,65 +23,86 @@ where G::Timestamp: IntegerTimestamp {
     where F: Fn(&G::Timestamp, &D)->G::Timestamp+'static;
     fn tumbling_window(&self, size: usize) -> Stream<G, D>;
     fn epoch_window(&self, size: usize, slide: usize) -> Stream<G, D>;
+    fn processing_time_window<C, K, H>(&self, size: usize, clock: C, key: K) -> Stream<G, ((H, usize), Vec<D>)>
+    where C: Clock+'static,
+          K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          D: Send;
+    fn event_time_window<F>(&self, size: usize, slide: usize, delay: usize, time: F) -> (Stream<G, (usize, Vec<D>)>, Stream<G, D>)
+    where F: Fn(&D)->usize+'static;
+    fn event_time_tumbling_window<F>(&self, size: usize, delay: usize, time: F) -> (Stream<G, (usize, Vec<D>)>, Stream<G, D>)
+    where F: Fn(&D)->usize+'static;
+    fn triggered_window<F>(&self, size: usize, slide: usize, trigger: Trigger, time: F) -> Stream<G, (usize, PaneKind, Vec<D>)>
+    where F: Fn(&D)->usize+'static;
+    fn count_window<K, H>(&self, size: usize, slide: usize, key: K) -> Stream<G, (H, Vec<D>)>
+    where K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          D: Send;
+    fn tumbling_count_window<K, H>(&self, size: usize, key: K) -> Stream<G, (H, Vec<D>)>
+    where K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          D: Send;
 }
 
 impl<G: Scope, D: Data> Window<G, D> for Stream<G, D>
 where G::Timestamp: IntegerTimestamp {
     fn window<F>(&self, size: usize, slide: usize, time: F) -> Stream<G, D>
     where F: Fn(&G::Timestamp, &D)->G::Timestamp+'static{
+        assert!(0 < size && 0 < slide, "Windows need a positive size and slide.");
         
         let mut window_parts = HashMap::new();
+        let mut windows: HashMap<usize, BTreeSet<usize>> = HashMap::new();
         let mut start_epoch = None;
         self.unary_notify(Pipeline, "Window", Vec::new(), move |input, output, notificator| {
             input.for_each(|cap, data| {
+                let epoch = cap.time().to_integer();
                 data.drain(..).for_each(|data|{
                     let time = time(cap.time(), &data).to_integer();
                     // Record the starting epoch in case we don't begin at zero.
                     if start_epoch.is_none() { start_epoch = Some(time); }
-                    // Push the data onto a partial window for the current time.
-                    let part = window_parts.entry(time).or_insert_with(Vec::new);
-                    part.push(data);
-                    // Calculate the next epochs on which this partial window would
-                    // be output in a slide, then notify on those times. We need to
-                    // do this for every possible future slide within the window
-                    // size to ensure slides are still being output properly when
-                    // the window is bigger than twice the slide and we get a lot
-                    // of empty input epochs.
                     let start = start_epoch.unwrap();
-                    for i in 0..size/slide {
-                        let target = if time-start < size {
-                            start+size-1
-                        } else {
-                            start+size+((time-start-size)/slide+1+i)*slide-1
-                        };
-                        notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(target)));
+                    // Records from before the first window cannot be placed.
+                    if time < start { return; }
+                    // Find all windows that contain this time. They start at
+                    // multiples of the slide within (time-size, time], though
+                    // if the slide exceeds the size there may be none at all.
+                    let offset = time - start;
+                    let first = if size <= offset { (offset - size) / slide + 1 } else { 0 };
+                    let mut contained = false;
+                    for k in first..offset/slide+1 {
+                        let window = start + k * slide;
+                        if time < window + size {
+                            contained = true;
+                            // Output the window on its last epoch, or right
+                            // away if the time lies in the past.
+                            let target = max(window + size - 1, epoch);
+                            notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(target)));
+                            windows.entry(target).or_insert_with(BTreeSet::new).insert(window);
+                        }
+                    }
+                    // Push the data onto a partial window for the current time.
+                    if contained {
+                        window_parts.entry(time).or_insert_with(Vec::new).push(data);
                     }
                 });
             });
             
             notificator.for_each(|cap, _, _| {
-                let end = cap.time().to_integer();
-                let mut time = end+1-size;
-                let slide_end = time+slide;
-                let mut window = Vec::new();
-                // Compute full window from partials. First gather parts
-                // that would fall out of the window and remove them.
-                while time < slide_end {
-                    if let Some(mut part) = window_parts.remove(&time) {
-                        window.append(&mut part);
+                let mut output = output.session(&cap);
+                let starts = windows.remove(&cap.time().to_integer()).unwrap_or_else(BTreeSet::new);
+                for &window in starts.iter() {
+                    // Gather and clone the parts of the full window.
+                    for time in window..window+size {
+                        if let Some(part) = window_parts.get(&time) {
+                            output.give_iterator(part.iter().cloned());
+                        }
                     }
-                    time += 1;
                 }
-                // Then gather and clone parts that will still be relevant
-                // later.
-                while time <= end {
-                    if let Some(part) = window_parts.get(&time) {
-                        part.iter().for_each(|entry| window.push(entry.clone()));
-                    }
-                    time += 1;
+                // Windows are output in order, so parts before the start of
+                // the next window are no longer needed.
+                if let Some(&last) = starts.iter().next_back() {
+                    window_parts.retain(|&time, _| last + slide <= time);
                 }
-                // Finally output the full window.
-                output.session(&cap).give_iterator(window.drain(..));
             });
         })
     }
@@ -101,6 +134,162 @@ where G::Timestamp: IntegerTimestamp {
     fn epoch_window(&self, size: usize, slide: usize) -> Stream<G, D> {
         self.window(size, slide, |t, _| t.clone())
     }
+
+    /// Groups the records per key into tumbling windows of processing time.
+    ///
+    /// The size is given in milliseconds of the clock, and records are
+    /// assigned to a window by the clock's time on arrival. A window is
+    /// emitted along with its key and start time at the first epoch that
+    /// completes after the clock passed the window's end. Once the input
+    /// runs dry, all windows that are still open are emitted.
+    fn processing_time_window<C, K, H>(&self, size: usize, clock: C, key: K) -> Stream<G, ((H, usize), Vec<D>)>
+    where C: Clock+'static,
+          K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          D: Send {
+        let mut windows = HashMap::new();
+
+        let (key, exchange) = exchange!(key);
+
+        self.unary_notify(exchange, "ProcessingTimeWindow", Vec::new(), move |input, output, notificator| {
+            input.for_each(|cap, data| {
+                for data in data.drain(..) {
+                    let start = clock.now() / size * size;
+                    windows.entry((key(&data), start)).or_insert_with(Vec::new).push(data);
+                }
+                notificator.notify_at(cap.retain());
+            });
+
+            notificator.for_each(|cap, _, notificator| {
+                let now = clock.now();
+                let done = notificator.frontier(0).is_empty();
+                let closed: Vec<_> = windows.keys().filter(|&&(_, start)| done || start + size <= now).cloned().collect();
+                let mut session = output.session(&cap);
+                for window in closed {
+                    let data = windows.remove(&window).unwrap();
+                    session.give((window, data));
+                }
+                // Keep checking the clock on the following epochs, even if
+                // no more records arrive for a while.
+                if !windows.is_empty() {
+                    let next = G::Timestamp::from_integer(cap.time().to_integer() + 1);
+                    notificator.notify_at(cap.delayed(&next));
+                }
+            });
+        })
+    }
+
+    /// Groups the records into sliding windows of event time.
+    ///
+    /// The event time of a record is given in epochs by the time function,
+    /// and the record is added to all windows of the given size and slide
+    /// that contain it. The watermark follows the input frontier, trailing
+    /// it by the delay to allow for records that are out of order: a window
+    /// is thus emitted along with its start once the frontier passes the
+    /// epoch delay epochs after its end. Records of which all windows were
+    /// already emitted are late, and are passed on to the second stream.
+    fn event_time_window<F>(&self, size: usize, slide: usize, delay: usize, time: F) -> (Stream<G, (usize, Vec<D>)>, Stream<G, D>)
+    where F: Fn(&D)->usize+'static {
+        let mut windows = HashMap::new();
+        // The epoch on which the window starting at the given time fires.
+        let fire = move |start: usize| start + size - 1 + delay;
+
+        let output = self.unary_notify(Pipeline, "EventTimeWindow", Vec::new(), move |input, output, notificator| {
+            input.for_each(|cap, data| {
+                let epoch = cap.time().to_integer();
+                let mut late = Vec::new();
+                for data in data.drain(..) {
+                    let t = time(&data);
+                    // The windows containing t start within (t-size, t]. If the
+                    // slide exceeds the size, t may not be in any window.
+                    let first = if size <= t { (t - size) / slide + 1 } else { 0 };
+                    let mut contained = false;
+                    let mut assigned = false;
+                    for start in (first..t/slide+1).map(|k| k * slide).filter(|&s| t < s + size) {
+                        contained = true;
+                        if fire(start) < epoch { continue; }
+                        windows.entry(start).or_insert_with(|| {
+                            notificator.notify_at(cap.delayed(&G::Timestamp::from_integer(fire(start))));
+                            Vec::new()
+                        }).push(data.clone());
+                        assigned = true;
+                    }
+                    if contained && !assigned { late.push(data); }
+                }
+                if !late.is_empty() {
+                    output.session(&cap).give_iterator(late.drain(..).map(EventTimeOutput::Late));
+                }
+            });
+
+            notificator.for_each(|cap, _, _| {
+                let start = cap.time().to_integer() + 1 - size - delay;
+                if let Some(window) = windows.remove(&start) {
+                    output.session(&cap).give(EventTimeOutput::Window(start, window));
+                }
+            });
+        });
+
+        (output.filter_map(|o| if let EventTimeOutput::Window(s, w) = o { Some((s, w)) } else { None }),
+         output.filter_map(|o| if let EventTimeOutput::Late(d) = o { Some(d) } else { None }))
+    }
+
+    fn event_time_tumbling_window<F>(&self, size: usize, delay: usize, time: F) -> (Stream<G, (usize, Vec<D>)>, Stream<G, D>)
+    where F: Fn(&D)->usize+'static {
+        self.event_time_window(size, size, delay, time)
+    }
+
+    /// Groups the records into sliding windows of event time that fire
+    /// according to the trigger.
+    ///
+    /// Every result is emitted along with the window's start and the kind of
+    /// firing it stems from. See trigger::triggered_windows for the details.
+    fn triggered_window<F>(&self, size: usize, slide: usize, trigger: Trigger, time: F) -> Stream<G, (usize, PaneKind, Vec<D>)>
+    where F: Fn(&D)->usize+'static {
+        trigger::triggered_windows(self, Pipeline, size, slide, trigger, time, |_| (), Vec::new(),
+                                   |d, mut w| { w.push(d); w },
+                                   |mut a, mut b| { a.append(&mut b); a })
+            .map(|((_, start), kind, window)| (start, kind, window))
+    }
+
+    /// Groups the records per key into sliding windows of a number of records.
+    ///
+    /// Every slide records of a key, the window of the last size records of
+    /// that key is emitted, or fewer if the key has not seen as many yet.
+    /// The windows are emitted on the epoch of the record that completes them.
+    fn count_window<K, H>(&self, size: usize, slide: usize, key: K) -> Stream<G, (H, Vec<D>)>
+    where K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          D: Send {
+        assert!(0 < size && 0 < slide, "Windows need a positive size and slide.");
+        let mut windows = HashMap::new();
+
+        let (key, exchange) = exchange!(key);
+
+        self.unary_stream(exchange, "CountWindow", move |input, output| {
+            input.for_each(|time, data| {
+                let mut session = output.session(&time);
+                for data in data.drain(..) {
+                    let key = key(&data);
+                    let &mut (ref mut window, ref mut count) = windows.entry(key.clone())
+                        .or_insert_with(|| (VecDeque::with_capacity(size), 0));
+                    if window.len() == size { window.pop_front(); }
+                    window.push_back(data);
+                    *count += 1;
+                    if *count == slide {
+                        *count = 0;
+                        session.give((key, window.iter().cloned().collect()));
+                    }
+                }
+            });
+        })
+    }
+
+    fn tumbling_count_window<K, H>(&self, size: usize, key: K) -> Stream<G, (H, Vec<D>)>
+    where K: Fn(&D)->H+'static,
+          H: Hash+Eq+Data+Clone,
+          D: Send {
+        self.count_window(size, size, key)
+    }
 }
 
 #[cfg(test)]
@@ -110,6 +299,10 @@ mod tests {
     use timely::dataflow::operators::{ToStream, Capture, Delay, Map};
     use timely::dataflow::operators::capture::Extract;
     use timely::progress::timestamp::RootTimestamp;
+    use clock::SimulatedClock;
+    use operators::{Trigger, Accumulation, PaneKind};
+    use rand::{Rng, StdRng, SeedableRng};
+    use std::collections::BTreeMap;
     
     #[test]
     fn epoch_window() {
@@ -147,6 +340,95 @@ mod tests {
         assert_eq!(data[2].1, vec!(1));
     }
     
+    #[test]
+    fn window_brute_force() {
+        let mut rng = StdRng::from_seed(&[0x51, 0x1D]);
+        for _ in 0..200 {
+            let size: usize = rng.gen_range(1, 8);
+            let slide: usize = rng.gen_range(1, 8);
+            let records: Vec<(u64, usize)> = (0..rng.gen_range(1, 20)).map(|i| (rng.gen_range(0, 15), i)).collect();
+            let input = records.clone();
+            let data = timely::example(move |scope| {
+                input.to_stream(scope)
+                    .delay(|d, _| RootTimestamp::new(d.0))
+                    .map(|d| d.1)
+                    .epoch_window(size, slide)
+                    .capture()
+            });
+
+            // Assign every record to every window that contains it.
+            let (size, slide) = (size as u64, slide as u64);
+            let start = records.iter().map(|r| r.0).min().unwrap();
+            let end = records.iter().map(|r| r.0).max().unwrap();
+            let mut expected = Vec::new();
+            let mut window = start;
+            while window <= end {
+                let mut contents: Vec<usize> = records.iter()
+                    .filter(|r| window <= r.0 && r.0 < window + size)
+                    .map(|r| r.1)
+                    .collect();
+                if !contents.is_empty() {
+                    contents.sort();
+                    expected.push((RootTimestamp::new(window + size - 1), contents));
+                }
+                window += slide;
+            }
+            assert_eq!(data.extract(), expected, "size {} slide {} records {:?}", size, slide, records);
+        }
+    }
+
+    #[test]
+    fn event_time_window_brute_force() {
+        let mut rng = StdRng::from_seed(&[0xE7, 0x1D]);
+        for _ in 0..200 {
+            let size: usize = rng.gen_range(1, 8);
+            let slide: usize = rng.gen_range(1, 8);
+            let delay: usize = rng.gen_range(0, 4);
+            // Records of an epoch, with an event time that may lie before or after it.
+            let records: Vec<(u64, usize, usize)> = (0..rng.gen_range(1, 20)).map(|i| (rng.gen_range(0, 15), rng.gen_range(0, 15), i)).collect();
+            let input = records.clone();
+            let (windows, late) = timely::example(move |scope| {
+                let (windows, late) = input.to_stream(scope)
+                    .delay(|d, _| RootTimestamp::new(d.0))
+                    .map(|d| (d.1, d.2))
+                    .event_time_window(size, slide, delay, |d| d.0);
+                (windows.map(|(s, w)| { let mut w: Vec<_> = w.into_iter().map(|d| d.1).collect(); w.sort(); (s, w) }).capture(),
+                 late.map(|d| d.1).capture())
+            });
+
+            // Assign every record to every window that contains it and has
+            // not fired yet by its epoch. The others in a window are late.
+            let mut expected_windows = BTreeMap::new();
+            let mut expected_late = BTreeMap::new();
+            for &(epoch, time, id) in records.iter() {
+                let mut contained = false;
+                let mut assigned = false;
+                let mut start = 0;
+                while start <= time {
+                    if time < start + size {
+                        contained = true;
+                        if epoch as usize <= start + size - 1 + delay {
+                            expected_windows.entry(start).or_insert_with(Vec::new).push(id);
+                            assigned = true;
+                        }
+                    }
+                    start += slide;
+                }
+                if contained && !assigned {
+                    expected_late.entry(epoch).or_insert_with(Vec::new).push(id);
+                }
+            }
+            let expected_windows: Vec<_> = expected_windows.into_iter()
+                .map(|(s, mut w)| { w.sort(); (RootTimestamp::new((s + size - 1 + delay) as u64), vec!((s, w))) })
+                .collect();
+            let expected_late: Vec<_> = expected_late.into_iter()
+                .map(|(e, mut l)| { l.sort(); (RootTimestamp::new(e), l) })
+                .collect();
+            assert_eq!(windows.extract(), expected_windows, "size {} slide {} delay {} records {:?}", size, slide, delay, records);
+            assert_eq!(late.extract(), expected_late, "size {} slide {} delay {} records {:?}", size, slide, delay, records);
+        }
+    }
+
     #[test]
     fn tumbling_window() {
         let data = timely::example(|scope| {
@@ -165,4 +447,111 @@ mod tests {
         assert_eq!(data[0].1, vec!(1, 2, 3, 4, 5));
         assert_eq!(data[1].1, vec!(6, 7));
     }
+
+    #[test]
+    fn processing_time_window() {
+        // Every record reads the clock once, and so does the notification
+        // at the end of the epoch, which thus happens at time 5. The window
+        // at 4 is still open then, but is emitted as the input is done.
+        let data = timely::example(|scope| {
+            vec!((1, 10), (2, 20), (1, 30), (1, 40), (2, 50))
+                .to_stream(scope)
+                .processing_time_window(2, SimulatedClock::new(1), |d| d.0)
+                .capture()
+        });
+
+        let mut data = data.extract();
+        assert_eq!(data.len(), 1);
+        data[0].1.sort();
+        assert_eq!(data[0].1, vec!(((1, 0), vec!((1, 10))),
+                                   ((1, 2), vec!((1, 30), (1, 40))),
+                                   ((2, 0), vec!((2, 20))),
+                                   ((2, 4), vec!((2, 50)))));
+    }
+
+    #[test]
+    fn event_time_window() {
+        let (windows, late) = timely::example(|scope| {
+            let (windows, late) = vec!((0, 1), (0, 3),
+                                       (1, 5), (1, 2),
+                                       (2, 8),
+                                       (5, 1), (5, 3))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .map(|d| d.1)
+                .event_time_window(4, 2, 1, |&t| t);
+            (windows.map(|(s, mut w)| { w.sort(); (s, w) }).capture(), late.capture())
+        });
+
+        let windows = windows.extract();
+        assert_eq!(windows[0], (RootTimestamp::new(4), vec!((0, vec!(1, 2, 3)))));
+        // The second record of epoch 5 is only late for the first window.
+        assert_eq!(windows[1], (RootTimestamp::new(6), vec!((2, vec!(2, 3, 3, 5)))));
+        assert_eq!(windows[2], (RootTimestamp::new(8), vec!((4, vec!(5)))));
+        assert_eq!(windows[3], (RootTimestamp::new(10), vec!((6, vec!(8)))));
+        assert_eq!(windows[4], (RootTimestamp::new(12), vec!((8, vec!(8)))));
+        assert_eq!(late.extract(), vec!((RootTimestamp::new(5), vec!(1))));
+    }
+
+    #[test]
+    fn event_time_window_gaps() {
+        // With a slide larger than the size, times 2 and 5 lie between the
+        // windows, so they are neither assigned nor late.
+        let (windows, late) = timely::example(|scope| {
+            let (windows, late) = vec!((0, 1), (0, 2),
+                                       (3, 3),
+                                       (6, 5), (6, 0))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .map(|d| d.1)
+                .event_time_window(2, 3, 0, |&t| t);
+            (windows.capture(), late.capture())
+        });
+
+        assert_eq!(windows.extract(), vec!((RootTimestamp::new(1), vec!((0, vec!(1)))),
+                                           (RootTimestamp::new(4), vec!((3, vec!(3))))));
+        assert_eq!(late.extract(), vec!((RootTimestamp::new(6), vec!(0))));
+    }
+
+    #[test]
+    fn triggered_window() {
+        let data = timely::example(|scope| {
+            let trigger = Trigger{ early_records: Some(2), lateness: 2, accumulation: Accumulation::Accumulating, ..Trigger::on_time() };
+            vec!((0, 0), (0, 1),
+                 (1, 2),
+                 (4, 3),
+                 (6, 1))
+                .to_stream(scope)
+                .delay(|d, _| RootTimestamp::new(d.0))
+                .map(|d| d.1)
+                .triggered_window(4, 4, trigger, |&t| t)
+                .capture()
+        });
+
+        // The last record is beyond the allowed lateness and dropped.
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(0), vec!((0, PaneKind::Early, vec!(0, 1)))),
+                                        (RootTimestamp::new(3), vec!((0, PaneKind::OnTime, vec!(0, 1, 2)))),
+                                        (RootTimestamp::new(4), vec!((0, PaneKind::Late, vec!(0, 1, 2, 3))))));
+    }
+
+    #[test]
+    fn count_window() {
+        let data = timely::example(|scope| {
+            (0..10).to_stream(scope)
+                .count_window(3, 2, |x| x % 2)
+                .capture()
+        });
+
+        assert_eq!(data.extract()[0].1, vec!((0, vec!(0, 2)), (0, vec!(2, 4, 6)),
+                                             (1, vec!(1, 3)), (1, vec!(3, 5, 7))));
+
+        let data = timely::example(|scope| {
+            (0..10).to_stream(scope)
+                .tumbling_count_window(2, |x| x % 2)
+                .capture()
+        });
+
+        assert_eq!(data.extract()[0].1, vec!((0, vec!(0, 2)), (0, vec!(4, 6)),
+                                             (1, vec!(1, 3)), (1, vec!(5, 7))));
+    }
 }
@@ -136,6 +136,18 @@ pub trait TestImpl : Sync+Send {
     fn run(&self, config: &Config, worker: &mut Root<Generic>) -> Result<Statistics>{
         // Construct the full flow.
         let (mut ins, mut out) = self.create_endpoints(config, worker.index(), worker.peers())?;
+        // Generate all epochs up front if requested, so that the measured
+        // latencies do not include the time spent generating the data.
+        if config.get_as_or("pregenerate", false) {
+            let mut epochs = Vec::new();
+            loop {
+                match ins.next() {
+                    Ok(epoch) => epochs.push(epoch),
+                    Err(e) => if is_out_of_data(&e) { break; } else { return Err(e); }
+                }
+            }
+            ins = Source::from(epochs);
+        }
         let mut input = InputHandle::new();
         let probe = worker.dataflow(|scope| {
             let mut probe = Handle::new();
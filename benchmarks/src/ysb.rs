@@ -1,21 +1,26 @@
 use config::Config;
-use endpoint::{self, Source, Drain, ToData, FromData, EventSource};
-use operators::{Window, Reduce};
-use rand::{self, Rng, SeedableRng};
+use distribution::Distribution;
+use endpoint::{self, Source, Drain, ToData, FromData, EventSource, EventDrain};
+use kvstore::{KVClient, Store};
+use operators::{Reduce, Join, JoinRetention, FilterMap, Trigger, Accumulation, PaneKind, IntegerTimestamp};
+use rand::{Rng, StdRng, SeedableRng};
 use serde_json;
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::fs::File;
 use std::fs;
-use std::io::{Result, Write};
+use std::io::{Result, Write, BufReader};
+use std::rc::Rc;
 use std::thread::{self, JoinHandle};
-use std::sync::RwLock;
+use std::sync::{RwLock, Mutex};
+use std::time::Instant;
 use test::{Test, TestImpl, Benchmark};
-use timely::dataflow::operators::{Map, Filter};
+use timely::dataflow::channels::pact::Pipeline;
+use timely::dataflow::operators::{Map, Filter, Unary, Concat};
 use timely::dataflow::scopes::{Root, Child};
-use timely::dataflow::{Stream};
+use timely::dataflow::{Stream, Scope};
 use timely::progress::timestamp::Timestamp;
 use timely_communication::allocator::Generic;
-use uuid::Uuid;
 
 #[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Abomonation)]
 struct Event {
@@ -44,12 +49,98 @@ impl FromData<usize> for Event{
     }
 }
 
+impl Event {
+    fn from(record: Record) -> Option<Event> {
+        match record {
+            Record::Event(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// An entry of the ad to campaign table.
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Abomonation)]
+struct Campaign {
+    ad_id: String,
+    campaign_id: String,
+}
+
+impl Campaign {
+    fn from(record: Record) -> Option<Campaign> {
+        match record {
+            Record::Campaign(c) => Some(c),
+            _ => None
+        }
+    }
+}
+
+/// The input for tests that receive the campaign table as a stream.
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize, Abomonation)]
+#[serde(tag = "type")]
+enum Record {
+    Campaign(Campaign),
+    Event(Event),
+}
+
 impl<T: Timestamp> FromData<T> for (String, usize) {
     fn from_data(&self, t: &T) -> String {
         format!("{:?} {:?}", t, self)
     }
 }
 
+/// Generates a random UUID-formatted string from the given RNG.
+///
+/// We cannot use Uuid::new_v4 for the campaign table, as that draws from
+/// the thread RNG and would thus differ between workers and processes.
+/// It is also a lot cheaper, which matters for generating events.
+fn seeded_uuid(rng: &mut StdRng) -> String {
+    format!("{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+            rng.gen::<u32>(), rng.gen::<u16>(), rng.gen::<u16>(),
+            rng.gen::<u16>(), rng.gen::<u64>() & 0xFFFF_FFFF_FFFF)
+}
+
+/// Generates the ad to campaign table deterministically from the seed.
+///
+/// Every worker and process calling this with the same configuration
+/// will receive the exact same table.
+fn generate_campaigns(config: &Config) -> HashMap<String, String> {
+    let seed = config.get_as_or("campaign-seed", 0xCAFE);
+    let campaigns = config.get_as_or("campaigns", 100);
+    let ads = config.get_as_or("ads", 10);
+    let mut rng = StdRng::from_seed(&[seed]);
+    let mut map = HashMap::new();
+    for _ in 0..campaigns {
+        let campaign_id = seeded_uuid(&mut rng);
+        for _ in 0..ads {
+            let ad_id = seeded_uuid(&mut rng);
+            map.insert(ad_id, campaign_id.clone());
+        }
+    }
+    map
+}
+
+/// Returns the ad to campaign table for this run.
+///
+/// If the input is read from files, or a campaigns file is given explicitly,
+/// the table is loaded from the campaigns.json written by generate_data.
+/// Otherwise it is generated from the campaign seed.
+fn campaign_table(config: &Config) -> Result<HashMap<String, String>> {
+    let file = config.get("campaigns-file").or_else(|| {
+        if config.get_or("input", "generated") == "file" {
+            Some(format!("{}/ysb/campaigns.json", config.get_or("data-dir", "data")))
+        } else {
+            None
+        }
+    });
+    match file {
+        Some(file) => {
+            let reader = BufReader::new(File::open(file)?);
+            serde_json::from_reader(reader).map_err(|e| e.into())
+        },
+        None => Ok(generate_campaigns(config))
+    }
+}
+
 struct Query {
     campaign_map: RwLock<HashMap<String, String>>
 }
@@ -69,10 +160,17 @@ impl TestImpl for Query {
 
     fn name(&self) -> &str { "Yahoo Streaming Benchmark" }
 
-    fn create_endpoints(&self, config: &Config, _index: usize, _workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
-        let gen = YSBGenerator::new(config);
-        let mut target = self.campaign_map.write().unwrap();
-        for (k, v) in &gen.map { target.insert(k.clone(), v.clone()); }
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        // Only the first worker to get here fills in the table. Since the
+        // table is the same for everyone, the rest can simply share it.
+        let map = {
+            let mut target = self.campaign_map.write().unwrap();
+            if target.is_empty() {
+                *target = campaign_table(config)?;
+            }
+            target.clone()
+        };
+        let gen = YSBGenerator::new(config, index, workers, map)?;
         Ok((Source::from_config(config, Source::new(Box::new(gen)))?,
             Drain::from_config(config)?))
     }
@@ -88,45 +186,424 @@ impl TestImpl for Query {
                      Some(id) => id.clone(),
                      None => String::from("UNKNOWN AD")
                  })
-            .tumbling_window(window_size)
-            .reduce_by(|campaign_id| campaign_id.clone(), 0, |_, count| count+1)
+            .window_reduce_by(window_size, window_size, |campaign_id| campaign_id.clone(),
+                              0, |_, count| count+1, |a, b| a+b)
+    }
+}
+
+/// This drain writes the windowed campaign counts into the key-value store.
+///
+/// Each count is stored under the campaign ID and the window's time, akin
+/// to how the original benchmark writes its windows back to Redis.
+struct StoreDrain {
+    client: Box<KVClient+Send>,
+}
+
+impl EventDrain<usize, (String, usize)> for StoreDrain {
+    fn next(&mut self, t: usize, d: Vec<(String, usize)>) {
+        for (campaign_id, count) in d {
+            self.client.set(&format!("{}:{}", campaign_id, t), &count.to_string())
+                .expect("YSB: Failed to write window to the store.");
+        }
+    }
+}
+
+/// This variant performs the campaign lookup and the result writes against
+/// an external key-value store, as the original benchmark does with Redis.
+///
+/// The store is selected through the kv-store option. Every worker uses
+/// its own connection for the lookups, and another for the drain.
+struct StoreQuery {
+    store: Mutex<Option<Store>>,
+    campaign_map: RwLock<HashMap<String, String>>,
+}
+
+impl StoreQuery {
+    fn new() -> Self {
+        StoreQuery {
+            store: Mutex::new(None),
+            campaign_map: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl TestImpl for StoreQuery {
+    type D = Event;
+    type DO = (String, usize);
+    type T = usize;
+
+    fn name(&self) -> &str { "Yahoo Streaming Benchmark Store" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        // The first worker of each process loads the campaign table, sets
+        // up the store and fills it with the table. Everyone else blocks
+        // until that's done, and then shares the table and the store.
+        let store = {
+            let mut store = self.store.lock().unwrap();
+            if store.is_none() {
+                let map = campaign_table(config)?;
+                let new = Store::from_config(config)?;
+                let mut client = new.connect()?;
+                for (ad_id, campaign_id) in &map {
+                    client.set(ad_id, campaign_id)?;
+                }
+                *self.campaign_map.write().unwrap() = map;
+                *store = Some(new);
+            }
+            store.clone().unwrap()
+        };
+        let map = self.campaign_map.read().unwrap().clone();
+        let drain = StoreDrain{ client: store.connect()? };
+        Ok((Source::from_config(config, Source::new(Box::new(YSBGenerator::new(config, index, workers, map)?)))?,
+            Drain::new(Box::new(drain))))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let window_size = config.get_as_or("window-size", 10) as usize;
+        // The store was set up and reached in create_endpoints already.
+        let store = self.store.lock().unwrap().clone().expect("YSB: Store was not set up.");
+        let mut client = store.connect().expect("YSB: Failed to connect to the store.");
+        stream
+            .filter(|x: &Event| x.event_type == "view")
+            .unary_stream(Pipeline, "CampaignLookup", move |input, output| {
+                input.for_each(|time, data| {
+                    output.session(&time).give_iterator(data.drain(..).map(|x| {
+                        client.get(&x.ad_id)
+                            .expect("YSB: Failed to look up campaign in the store.")
+                            .unwrap_or_else(|| String::from("UNKNOWN AD"))
+                    }));
+                });
+            })
+            .window_reduce_by(window_size, window_size, |campaign_id| campaign_id.clone(),
+                              0, |_, count| count+1, |a, b| a+b)
+    }
+}
+
+/// This source feeds the campaign table into the dataflow alongside the events.
+///
+/// The full table is emitted with the first epoch. After that, a number of
+/// new campaigns are added and ads reassigned to other campaigns on every
+/// epoch. The changes are only emitted by the first worker, so that each of
+/// them enters the dataflow exactly once.
+struct TableSource {
+    events: Source<usize, Event>,
+    pending: Vec<Campaign>,
+    ads: Vec<String>,
+    campaigns: Vec<String>,
+    updates: usize,
+    additions: usize,
+    ads_per_campaign: usize,
+    rng: StdRng,
+    leader: bool,
+}
+
+impl TableSource {
+    fn new(config: &Config, index: usize, map: HashMap<String, String>, events: Source<usize, Event>) -> Self {
+        // Sort to make the changes independent of the map's iteration order.
+        let mut ads: Vec<String> = map.keys().cloned().collect();
+        ads.sort();
+        let mut campaigns: Vec<String> = map.values().cloned().collect();
+        campaigns.sort();
+        campaigns.dedup();
+        let pending = ads.iter()
+            .map(|ad_id| Campaign{ ad_id: ad_id.clone(), campaign_id: map[ad_id].clone() })
+            .collect();
+        TableSource {
+            events: events,
+            pending: pending,
+            ads: ads,
+            campaigns: campaigns,
+            updates: config.get_as_or("campaign-updates", 0),
+            additions: config.get_as_or("campaign-additions", 0),
+            ads_per_campaign: config.get_as_or("ads", 10),
+            rng: StdRng::from_seed(&[config.get_as_or("campaign-seed", 0xCAFE), 1]),
+            leader: index == 0,
+        }
+    }
+
+    /// Computes the table changes to be emitted with the next epoch.
+    fn change_table(&mut self) {
+        if self.ads.is_empty() { return; }
+        for _ in 0..self.additions {
+            let campaign_id = seeded_uuid(&mut self.rng);
+            for _ in 0..self.ads_per_campaign {
+                let ad_id = self.rng.choose(&self.ads).unwrap().clone();
+                self.pending.push(Campaign{ ad_id: ad_id, campaign_id: campaign_id.clone() });
+            }
+            self.campaigns.push(campaign_id);
+        }
+        for _ in 0..self.updates {
+            let ad_id = self.rng.choose(&self.ads).unwrap().clone();
+            let campaign_id = self.rng.choose(&self.campaigns).unwrap().clone();
+            self.pending.push(Campaign{ ad_id: ad_id, campaign_id: campaign_id });
+        }
+    }
+}
+
+impl EventSource<usize, Record> for TableSource {
+    fn next(&mut self) -> Result<(usize, Vec<Record>)> {
+        let (t, events) = self.events.next()?;
+        let mut data = Vec::with_capacity(events.len() + self.pending.len());
+        if self.leader {
+            data.extend(self.pending.drain(..).map(Record::Campaign));
+            self.change_table();
+        }
+        data.extend(events.into_iter().map(Record::Event));
+        Ok((t, data))
+    }
+}
+
+/// This variant receives the campaign table as a second stream and joins
+/// the events against it, rather than capturing a copy of the table.
+///
+/// Since the join keeps the latest campaign for each ad, the table can be
+/// changed and extended while the test is running.
+struct JoinQuery {}
+
+impl JoinQuery {
+    fn new() -> Self {
+        JoinQuery {}
+    }
+}
+
+impl TestImpl for JoinQuery {
+    type D = Record;
+    type DO = (String, usize);
+    type T = usize;
+
+    fn name(&self) -> &str { "Yahoo Streaming Benchmark Join" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        let map = campaign_table(config)?;
+        let events = Source::from_config(config, Source::new(Box::new(YSBGenerator::new(config, index, workers, map.clone())?)))?;
+        Ok((Source::new(Box::new(TableSource::new(config, index, map, events))),
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let window_size = config.get_as_or("window-size", 10) as usize;
+        let campaigns = stream
+            .filter_map(|r| Campaign::from(r));
+        let views = stream
+            .filter_map(|r| Event::from(r))
+            .filter(|x: &Event| x.event_type == "view");
+        let latest = JoinRetention{ max_per_key: Some(1), ..JoinRetention::unbounded() };
+        campaigns
+            .bounded_left_join(&views, latest, |c| c.ad_id.clone(), |e| e.ad_id.clone(), |c, _| c.campaign_id)
+            .0
+            .window_reduce_by(window_size, window_size, |campaign_id| campaign_id.clone(),
+                              0, |_, count| count+1, |a, b| a+b)
+    }
+}
+
+/// This variant joins the events against the campaign table like the join
+/// variant, but replicates the table to every worker rather than exchanging
+/// the events by their ad.
+struct BroadcastJoinQuery {}
+
+impl BroadcastJoinQuery {
+    fn new() -> Self {
+        BroadcastJoinQuery {}
+    }
+}
+
+impl TestImpl for BroadcastJoinQuery {
+    type D = Record;
+    type DO = (String, usize);
+    type T = usize;
+
+    fn name(&self) -> &str { "Yahoo Streaming Benchmark Broadcast Join" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        let map = campaign_table(config)?;
+        let events = Source::from_config(config, Source::new(Box::new(YSBGenerator::new(config, index, workers, map.clone())?)))?;
+        Ok((Source::new(Box::new(TableSource::new(config, index, map, events))),
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let window_size = config.get_as_or("window-size", 10) as usize;
+        let campaigns = stream
+            .filter_map(|r| Campaign::from(r));
+        let views = stream
+            .filter_map(|r| Event::from(r))
+            .filter(|x: &Event| x.event_type == "view");
+        let latest = JoinRetention{ max_per_key: Some(1), ..JoinRetention::unbounded() };
+        campaigns
+            .bounded_broadcast_join(&views, latest, |c| c.ad_id.clone(), |e| e.ad_id.clone(), |c, _| c.campaign_id)
+            .0
+            .window_reduce_by(window_size, window_size, |campaign_id| campaign_id.clone(),
+                              0, |_, count| count+1, |a, b| a+b)
+    }
+}
+
+/// Describes why a window result was emitted.
+#[derive(Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug, Abomonation)]
+enum Firing {
+    /// The window was complete when the input passed its end.
+    OnTime,
+    /// Events arrived after the window fired, but within the allowed lateness.
+    Late,
+    /// Events arrived after the allowed lateness and were not counted.
+    Dropped,
+}
+
+/// A result or update of a campaign's event-time window.
+#[derive(Eq, PartialEq, Clone, Debug, Abomonation)]
+struct WindowUpdate {
+    campaign_id: String,
+    /// The start of the window in event time milliseconds.
+    window: usize,
+    /// The current count of the window, or the number of dropped events.
+    count: usize,
+    /// The milliseconds between the end of the window and this update.
+    latency: i64,
+    firing: Firing,
+}
+
+impl FromData<usize> for WindowUpdate {
+    fn from_data(&self, t: &usize) -> String {
+        format!("{} {:?}", t, self)
+    }
+}
+
+/// The milliseconds between the end of a window and now, relative to the
+/// anchor that ties event time to the wall clock.
+fn latency(anchor: Option<(Instant, usize)>, end: usize) -> i64 {
+    let (start, time) = anchor.unwrap();
+    let elapsed = start.elapsed();
+    let elapsed = (elapsed.as_secs() * 1000) as i64 + (elapsed.subsec_nanos() / 1_000_000) as i64;
+    elapsed - end as i64 + time as i64
+}
+
+/// Counts the events per campaign in tumbling windows of event time.
+///
+/// The windows are fired once the epochs pass their end, and are kept for
+/// another `lateness` epochs after that, during which late events cause an
+/// update of their window at the end of the epoch. Events that arrive later
+/// still are reported as dropped.
+///
+/// Just like in the original benchmark, the latency of an update is the
+/// time between the end of its window and the moment it was emitted. For
+/// this to be comparable, event time is anchored to the wall clock at the
+/// first event. Negative latencies thus mean that the input was replayed
+/// faster than real time.
+fn event_time_windows<G: Scope>(stream: &Stream<G, (String, usize)>, size: usize, lateness: usize) -> Stream<G, WindowUpdate>
+where G::Timestamp: IntegerTimestamp {
+    let anchor = Rc::new(Cell::new(None));
+    let anchor_ = anchor.clone();
+    // The windows are aligned to multiples of their size in epochs.
+    let end = move |event_time: usize| (event_time / 1000 / size + 1) * size * 1000;
+
+    // Mark the events that are beyond the allowed lateness, as the windows
+    // drop those without notice.
+    let checked = stream.unary_stream(Pipeline, "Lateness", move |input, output| {
+        input.for_each(|cap, data| {
+            let epoch = cap.time().to_integer();
+            output.session(&cap).give_iterator(data.drain(..).map(|(campaign_id, event_time)| {
+                if anchor_.get().is_none() { anchor_.set(Some((Instant::now(), event_time))); }
+                let dropped = end(event_time) / 1000 + lateness <= epoch;
+                (campaign_id, event_time, dropped)
+            }));
+        });
+    });
+
+    let trigger = Trigger{ lateness: lateness, accumulation: Accumulation::Accumulating, ..Trigger::on_time() };
+    let anchor_ = anchor.clone();
+    let updates = checked
+        .filter(|x| !x.2)
+        .triggered_window_reduce_by(size, size, trigger, |x| x.1 / 1000, |x| x.0.clone(),
+                                    0, |_, count| count+1, |a, b| a+b)
+        .map(move |((campaign_id, start), kind, count)| WindowUpdate {
+            campaign_id: campaign_id, window: start * 1000, count: count,
+            latency: latency(anchor_.get(), (start + size) * 1000),
+            firing: if kind == PaneKind::OnTime { Firing::OnTime } else { Firing::Late } });
+
+    let dropped = checked
+        .filter(|x| x.2)
+        .reduce(|x| (x.0.clone(), end(x.1)), 0, |_, count| count+1, move |(campaign_id, window_end), count, _| WindowUpdate {
+            campaign_id: campaign_id, window: window_end - size * 1000, count: count,
+            latency: latency(anchor.get(), window_end), firing: Firing::Dropped });
+
+    updates.concat(&dropped)
+}
+
+/// This variant windows the events by their event time instead of by the
+/// epoch they arrive in, and reports updates to already fired windows.
+struct EventTimeQuery {
+    campaign_map: RwLock<HashMap<String, String>>
+}
+
+impl EventTimeQuery {
+    fn new() -> Self {
+        EventTimeQuery {
+            campaign_map: RwLock::new(HashMap::new())
+        }
+    }
+}
+
+impl TestImpl for EventTimeQuery {
+    type D = Event;
+    type DO = WindowUpdate;
+    type T = usize;
+
+    fn name(&self) -> &str { "Yahoo Streaming Benchmark Event Time" }
+
+    fn create_endpoints(&self, config: &Config, index: usize, workers: usize) -> Result<(Source<Self::T, Self::D>, Drain<Self::T, Self::DO>)> {
+        let map = {
+            let mut target = self.campaign_map.write().unwrap();
+            if target.is_empty() {
+                *target = campaign_table(config)?;
+            }
+            target.clone()
+        };
+        let gen = YSBGenerator::new(config, index, workers, map)?;
+        Ok((Source::from_config(config, Source::new(Box::new(gen)))?,
+            Drain::from_config(config)?))
+    }
+
+    fn construct_dataflow<'scope>(&self, config: &Config, stream: &Stream<Child<'scope, Root<Generic>, Self::T>, Self::D>) -> Stream<Child<'scope, Root<Generic>, Self::T>, Self::DO> {
+        let window_size = config.get_as_or("window-size", 10) as usize;
+        let lateness = config.get_as_or("allowed-lateness", 0) as usize;
+        let table = self.campaign_map.read().unwrap().clone();
+        let windowed = stream
+            .filter(|x: &Event| x.event_type == "view")
+            .map(move |x|
+                 (match table.get(&x.ad_id){
+                     Some(id) => id.clone(),
+                     None => String::from("UNKNOWN AD")
+                 }, x.event_time));
+        event_time_windows(&windowed, window_size, lateness)
     }
 }
 
 #[derive(Clone)]
 struct YSBGenerator {
-    map: HashMap<String, String>,
+    ads: Vec<String>,
+    ad_distribution: Distribution,
+    rng: StdRng,
     time: f64,
     timestep: f64,
     max_time: f64,
 }
 
 impl YSBGenerator {
-    fn new(config: &Config) -> Self {
-        let index = config.get_as_or("worker-index", 0);
-        let threads = config.get_as_or("threads", 1);
-        let campaigns = config.get_as_or("campaigns", 100);
-        let ads = config.get_as_or("ads", 10);
+    fn new(config: &Config, index: usize, workers: usize, map: HashMap<String, String>) -> Result<Self> {
         let seconds = config.get_as_or("seconds", 60);
         let events_per_second = config.get_as_or("events-per-second", 100_000);
-        let timestep = (1000 * threads) as f64 / events_per_second as f64;
-        
-        // Generate campaigns map
-        let mut map = HashMap::new();
-        for _ in 0..campaigns {
-            let campaign_id = format!("{}", Uuid::new_v4());
-            for _ in 0..ads {
-                let ad_id = format!("{}", Uuid::new_v4());
-                map.insert(ad_id, campaign_id.clone());
-            }
-        }
+        let timestep = (1000 * workers) as f64 / events_per_second as f64;
+        // Sort to make the choice of ads independent of the map's iteration order.
+        let mut ads: Vec<String> = map.into_iter().map(|(ad_id, _)| ad_id).collect();
+        ads.sort();
 
-        YSBGenerator{
-            map: map,
-            time: 1.0+(index*1000/threads) as f64,
+        Ok(YSBGenerator{
+            ad_distribution: Distribution::from_config(config, "ad", ads.len(), "uniform")?,
+            ads: ads,
+            rng: StdRng::from_seed(&[0xDEAD, 0xBEEF, 0xFEED, index]),
+            time: 1.0+(index*1000/workers) as f64,
             timestep: timestep,
             max_time: (seconds * 1000) as f64,
-        }
+        })
     }
 }
 
@@ -134,16 +611,16 @@ impl EventSource<usize, Event> for YSBGenerator {
     fn next(&mut self) -> Result<(usize, Vec<Event>)> {
         const AD_TYPES: [&str; 5] = ["banner", "modal", "sponsored-search", "mail", "mobile"];
         const EVENT_TYPES: [&str; 3] = ["view", "click", "purchase"];
-        let mut rng = rand::StdRng::from_seed(&[0xDEAD, 0xBEEF, 0xFEED]); // Predictable RNG clutch
+        let rng = &mut self.rng;
         let mut data = Vec::with_capacity((1000.0 / self.timestep) as usize);
         let epoch = self.time as usize / 1000;
         
         while self.time < ((epoch+1)*1000) as f64
             && self.time < self.max_time as f64 {
             data.push(Event {
-                user_id: format!("{}", Uuid::new_v4()),
-                page_id: format!("{}", Uuid::new_v4()),
-                ad_id: self.map.keys().nth(rng.gen_range(0, self.map.len())).unwrap().clone(),
+                user_id: seeded_uuid(rng),
+                page_id: seeded_uuid(rng),
+                ad_id: self.ads[self.ad_distribution.sample(rng, epoch)].clone(),
                 ad_type: String::from(*rng.choose(&AD_TYPES).unwrap()),
                 event_type: String::from(*rng.choose(&EVENT_TYPES).unwrap()),
                 event_time: self.time as usize,
@@ -182,14 +659,22 @@ impl Benchmark for YSB {
         println!("Generating {} events/s for {}s over {} partitions for {} campaigns with {} ads each.",
                  events_per_second, seconds, partitions, campaigns, ads);
         
-        let generator = YSBGenerator::new(config);
+        let map = generate_campaigns(config);
+        // Each partition gets its own worker index, and thus its own slice of time and RNG.
+        let generator = |p: usize| YSBGenerator::new(config, p, partitions, map.clone());
+
+        if config.get_as_or("benchmark", false) {
+            let generators = (0..partitions).map(|p| generator(p)).collect::<Result<Vec<_>>>()?;
+            return endpoint::benchmark_generators(generators);
+        }
+
         let campaign_file = File::create(format!("{}/campaigns.json", &data_dir))?;
-        serde_json::to_writer(campaign_file, &generator.map)?;
+        serde_json::to_writer(campaign_file, &map)?;
         
         // Generate events
         let mut threads: Vec<JoinHandle<Result<()>>> = Vec::new();
         for p in 0..partitions {
-            let mut generator = generator.clone();
+            let mut generator = generator(p)?;
             let mut file = File::create(format!("{}/events-{}.json", &data_dir, p))?;;
             threads.push(thread::spawn(move || {
                 loop{
@@ -208,6 +693,43 @@ impl Benchmark for YSB {
     }
 
     fn tests(&self) -> Vec<Box<Test>> {
-        vec![Box::new(Query::new())]
+        vec![Box::new(Query::new()),
+             Box::new(StoreQuery::new()),
+             Box::new(JoinQuery::new()),
+             Box::new(BroadcastJoinQuery::new()),
+             Box::new(EventTimeQuery::new())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use timely;
+    use timely::dataflow::operators::{ToStream, Capture, Delay, Map};
+    use timely::dataflow::operators::capture::Extract;
+    use timely::progress::timestamp::RootTimestamp;
+
+    #[test]
+    fn event_time_windows() {
+        // Windows of two seconds that accept late events for another epoch.
+        let data = timely::example(|scope| {
+            let events = vec!((0, ("a", 100)), (0, ("a", 1500)), (0, ("b", 900)),
+                              (2, ("a", 1900)), (2, ("a", 2100)),
+                              (3, ("b", 1200)),
+                              (4, ("a", 3000)), (4, ("b", 1800)))
+                .to_stream(scope)
+                .delay(|e, _| RootTimestamp::new(e.0))
+                .map(|e| (String::from((e.1).0), (e.1).1));
+            super::event_time_windows(&events, 2, 1)
+                .map(|u| (u.campaign_id, u.window, u.count, u.firing))
+                .capture()
+        });
+
+        let a = String::from("a");
+        let b = String::from("b");
+        assert_eq!(data.extract(), vec!((RootTimestamp::new(1), vec!((a.clone(), 0, 2, Firing::OnTime), (b.clone(), 0, 1, Firing::OnTime))),
+                                        (RootTimestamp::new(2), vec!((a.clone(), 0, 3, Firing::Late))),
+                                        (RootTimestamp::new(3), vec!((a.clone(), 2000, 1, Firing::OnTime), (b.clone(), 0, 1, Firing::Dropped))),
+                                        (RootTimestamp::new(4), vec!((a.clone(), 2000, 2, Firing::Late), (b.clone(), 0, 1, Firing::Dropped)))));
     }
 }